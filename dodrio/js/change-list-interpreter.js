@@ -9,6 +9,9 @@ function string(mem, pointer, length) {
   return decoder.decode(buf);
 }
 
+// Scratch space for reassembling `f64`s from pairs of `u32` immediates.
+const NUMBER_VIEW = new DataView(new ArrayBuffer(8));
+
 const OP_TABLE = [
   // 0
   function setText(interpreter, mem8, mem32, i) {
@@ -25,9 +28,11 @@ const OP_TABLE = [
     let sibling = node.nextSibling;
     while (sibling) {
       const temp = sibling.nextSibling;
+      interpreter.clearNodeRefsIn(sibling);
       sibling.remove();
       sibling = temp;
     }
+    interpreter.clearNodeRefsIn(node);
     node.remove();
     return i;
   },
@@ -36,6 +41,7 @@ const OP_TABLE = [
   function replaceWith(interpreter, mem8, mem32, i) {
     const newNode = interpreter.stack.pop();
     const oldNode = interpreter.stack.pop();
+    interpreter.clearNodeRefsIn(oldNode);
     oldNode.replaceWith(newNode);
     interpreter.stack.push(newNode);
     return i;
@@ -249,6 +255,7 @@ const OP_TABLE = [
     const n = mem32[i++];
     const parent = top(interpreter.stack);
     const child = parent.childNodes[n];
+    interpreter.clearNodeRefsIn(child);
     child.remove();
     return i;
   },
@@ -275,6 +282,170 @@ const OP_TABLE = [
     const template = interpreter.getTemplate(id);
     interpreter.stack.push(template.cloneNode(true));
     return i;
+  },
+
+  // 26
+  function createComment(interpreter, mem8, mem32, i) {
+    const pointer = mem32[i++];
+    const length = mem32[i++];
+    const text = string(mem8, pointer, length);
+    interpreter.stack.push(document.createComment(text));
+    return i;
+  },
+
+  // 27
+  function pushPortalChild(interpreter, mem8, mem32, i) {
+    const index = mem32[i++];
+    const placeholder = top(interpreter.stack);
+    interpreter.stack.push(interpreter.portals.get(placeholder)[index]);
+    return i;
+  },
+
+  // 28
+  function mountPortal(interpreter, mem8, mem32, i) {
+    const targetId = mem32[i++];
+    const index = mem32[i++];
+    const target = interpreter.getPortalTarget(targetId);
+    const child = interpreter.stack.pop();
+    const placeholder = top(interpreter.stack);
+    let children = interpreter.portals.get(placeholder);
+    if (children === undefined) {
+      children = [];
+      interpreter.portals.set(placeholder, children);
+    }
+    if (child.parentNode !== target) {
+      // Keep the portal's children in order.
+      const next = index > 0 ? children[index - 1].nextSibling : null;
+      target.insertBefore(child, next);
+    }
+    children[index] = child;
+    return i;
+  },
+
+  // 29
+  function setNodeRef(interpreter, mem8, mem32, i) {
+    const id = mem32[i++];
+    const el = top(interpreter.stack);
+    interpreter.nodeRefs.set(id, el);
+    interpreter.nodeRefsTrampoline(id, el);
+    return i;
+  },
+
+  // 30
+  function removeNodeRef(interpreter, mem8, mem32, i) {
+    const id = mem32[i++];
+    if (interpreter.nodeRefs.get(id) === top(interpreter.stack)) {
+      interpreter.nodeRefs.delete(id);
+      interpreter.nodeRefsTrampoline(id, null);
+    }
+    return i;
+  },
+
+  // 31
+  function setInnerHtml(interpreter, mem8, mem32, i) {
+    const pointer = mem32[i++];
+    const length = mem32[i++];
+    const html = string(mem8, pointer, length);
+    top(interpreter.stack).innerHTML = html;
+    return i;
+  },
+
+  // 32
+  function setProperty(interpreter, mem8, mem32, i) {
+    const nameId = mem32[i++];
+    const kind = mem32[i++];
+    const a = mem32[i++];
+    const b = mem32[i++];
+    const name = interpreter.getCachedString(nameId);
+    let value;
+    switch (kind) {
+      case 0:
+        value = string(mem8, a, b);
+        break;
+      case 1:
+        value = a !== 0;
+        break;
+      default:
+        NUMBER_VIEW.setUint32(0, a, true);
+        NUMBER_VIEW.setUint32(4, b, true);
+        value = NUMBER_VIEW.getFloat64(0, true);
+        break;
+    }
+    // Compare against the live property, which the user may have changed,
+    // and only assign it when it differs, so that e.g. an input's cursor
+    // doesn't jump.
+    const node = top(interpreter.stack);
+    if (node[name] !== value) {
+      node[name] = value;
+    }
+    return i;
+  },
+
+  // 33
+  function removeProperty(interpreter, mem8, mem32, i) {
+    const nameId = mem32[i++];
+    const name = interpreter.getCachedString(nameId);
+    const node = top(interpreter.stack);
+    switch (typeof node[name]) {
+      case "string":
+        node[name] = "";
+        break;
+      case "boolean":
+        node[name] = false;
+        break;
+      case "number":
+        node[name] = 0;
+        break;
+      default:
+        node[name] = null;
+        break;
+    }
+    return i;
+  },
+
+  // 34
+  function setStyleProperty(interpreter, mem8, mem32, i) {
+    const nameId = mem32[i++];
+    const valueId = mem32[i++];
+    const name = interpreter.getCachedString(nameId);
+    const value = interpreter.getCachedString(valueId);
+    top(interpreter.stack).style.setProperty(name, value);
+    return i;
+  },
+
+  // 35
+  function removeStyleProperty(interpreter, mem8, mem32, i) {
+    const nameId = mem32[i++];
+    const name = interpreter.getCachedString(nameId);
+    top(interpreter.stack).style.removeProperty(name);
+    return i;
+  },
+
+  // 36
+  function addClass(interpreter, mem8, mem32, i) {
+    const classId = mem32[i++];
+    const className = interpreter.getCachedString(classId);
+    top(interpreter.stack).classList.add(className);
+    return i;
+  },
+
+  // 37
+  function removeClass(interpreter, mem8, mem32, i) {
+    const classId = mem32[i++];
+    const className = interpreter.getCachedString(classId);
+    top(interpreter.stack).classList.remove(className);
+    return i;
+  },
+
+  // 38
+  function truncatePortal(interpreter, mem8, mem32, i) {
+    const len = mem32[i++];
+    const placeholder = top(interpreter.stack);
+    for (const child of interpreter.portals.get(placeholder).splice(len)) {
+      child.remove();
+      interpreter.clearNodeRefsIn(child);
+    }
+    return i;
   }
 ];
 
@@ -287,11 +458,27 @@ export class ChangeListInterpreter {
     this.strings = new Map();
     this.temporaries = [];
     this.templates = new Map();
+    this.portalTargets = new Map();
+    this.portals = new Map();
+    this.nodeRefs = new Map();
+    this.nodeRefsTrampoline = null;
   }
 
   unmount() {
     this.trampoline.mounted = false;
 
+    // Portal children live outside of our container, so they won't be cleaned
+    // up along with it.
+    for (const children of this.portals.values()) {
+      for (const child of children) {
+        child.remove();
+      }
+    }
+
+    for (const id of this.nodeRefs.keys()) {
+      this.nodeRefsTrampoline(id, null);
+    }
+
     // Null out all of our properties just to ensure that if we mistakenly ever
     // call a method on this instance again, it will throw.
     this.trampoline = null;
@@ -301,6 +488,10 @@ export class ChangeListInterpreter {
     this.strings = null;
     this.temporaries = null;
     this.templates = null;
+    this.portalTargets = null;
+    this.portals = null;
+    this.nodeRefs = null;
+    this.nodeRefsTrampoline = null;
   }
 
   addChangeListRange(start, len) {
@@ -313,7 +504,7 @@ export class ChangeListInterpreter {
       return;
     }
 
-    this.stack.push(this.container.firstChild);
+    this.stack.push(this.container);
     const mem8 = new Uint8Array(memory.buffer);
     const mem32 = new Uint32Array(memory.buffer);
 
@@ -326,6 +517,48 @@ export class ChangeListInterpreter {
     this.ranges.length = 0;
     this.stack.length = 0;
     this.temporaries.length = 0;
+
+    this.removeDetachedPortals();
+  }
+
+  // Remove the children of portals whose placeholders were removed from the
+  // DOM by this change list. A placeholder is still attached if it is inside
+  // our container, or inside the child of another attached portal.
+  removeDetachedPortals() {
+    if (this.portals.size == 0) {
+      return;
+    }
+
+    const attached = new Set();
+    let changed = true;
+    while (changed) {
+      changed = false;
+      for (const placeholder of this.portals.keys()) {
+        if (attached.has(placeholder)) {
+          continue;
+        }
+        let isAttached = this.container.contains(placeholder);
+        for (const other of attached) {
+          isAttached =
+            isAttached ||
+            this.portals.get(other).some(child => child.contains(placeholder));
+        }
+        if (isAttached) {
+          attached.add(placeholder);
+          changed = true;
+        }
+      }
+    }
+
+    for (const [placeholder, children] of this.portals) {
+      if (!attached.has(placeholder)) {
+        this.portals.delete(placeholder);
+        for (const child of children) {
+          child.remove();
+          this.clearNodeRefsIn(child);
+        }
+      }
+    }
   }
 
   applyChangeRange(mem8, mem32, start, len) {
@@ -356,6 +589,29 @@ export class ChangeListInterpreter {
     return this.templates.get(id);
   }
 
+  addPortalTarget(id, target) {
+    this.portalTargets.set(id, target);
+  }
+
+  getPortalTarget(id) {
+    return this.portalTargets.get(id);
+  }
+
+  // Clear the node refs that are attached to `node` or its descendants,
+  // because `node` is being removed.
+  clearNodeRefsIn(node) {
+    for (const [id, el] of this.nodeRefs) {
+      if (node.contains(el)) {
+        this.nodeRefs.delete(id);
+        this.nodeRefsTrampoline(id, null);
+      }
+    }
+  }
+
+  initNodeRefsTrampoline(trampoline) {
+    this.nodeRefsTrampoline = trampoline;
+  }
+
   initEventsTrampoline(trampoline) {
     this.trampoline = trampoline;
     trampoline.mounted = true;
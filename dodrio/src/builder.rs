@@ -1,7 +1,13 @@
 //! Helpers for building virtual DOM nodes.
 
-use crate::{node::NodeKey, Attribute, Listener, Node, RootRender, VdomWeak};
+use crate::{
+    node::{ElementNode, LifecycleHook, NodeKey, RawHtmlNode},
+    node_ref::NodeRefId,
+    Attribute, Listener, Node, NodeRef, Property, PropertyValue, RenderContext, RootRender, Style,
+    VdomWeak,
+};
 use bumpalo::Bump;
+use std::panic::{self, AssertUnwindSafe};
 
 /// A virtual DOM element builder.
 ///
@@ -20,8 +26,14 @@ where
     tag_name: &'a str,
     listeners: Listeners,
     attributes: Attributes,
+    properties: bumpalo::collections::Vec<'a, Property<'a>>,
+    styles: bumpalo::collections::Vec<'a, Style<'a>>,
+    classes: bumpalo::collections::Vec<'a, &'a str>,
     children: Children,
     namespace: Option<&'a str>,
+    node_ref: Option<NodeRefId>,
+    on_mount: Option<LifecycleHook<'a>>,
+    on_unmount: Option<LifecycleHook<'a>>,
 }
 
 impl<'a>
@@ -66,8 +78,14 @@ impl<'a>
             tag_name,
             listeners: bumpalo::collections::Vec::new_in(bump),
             attributes: bumpalo::collections::Vec::new_in(bump),
+            properties: bumpalo::collections::Vec::new_in(bump),
+            styles: bumpalo::collections::Vec::new_in(bump),
+            classes: bumpalo::collections::Vec::new_in(bump),
             children: bumpalo::collections::Vec::new_in(bump),
             namespace: None,
+            node_ref: None,
+            on_mount: None,
+            on_unmount: None,
         }
     }
 }
@@ -116,8 +134,14 @@ where
             tag_name: self.tag_name,
             listeners,
             attributes: self.attributes,
+            properties: self.properties,
+            styles: self.styles,
+            classes: self.classes,
             children: self.children,
             namespace: self.namespace,
+            node_ref: self.node_ref,
+            on_mount: self.on_mount,
+            on_unmount: self.on_unmount,
         }
     }
 
@@ -155,8 +179,14 @@ where
             tag_name: self.tag_name,
             listeners: self.listeners,
             attributes,
+            properties: self.properties,
+            styles: self.styles,
+            classes: self.classes,
             children: self.children,
             namespace: self.namespace,
+            node_ref: self.node_ref,
+            on_mount: self.on_mount,
+            on_unmount: self.on_unmount,
         }
     }
 
@@ -194,8 +224,14 @@ where
             tag_name: self.tag_name,
             listeners: self.listeners,
             attributes: self.attributes,
+            properties: self.properties,
+            styles: self.styles,
+            classes: self.classes,
             children,
             namespace: self.namespace,
+            node_ref: self.node_ref,
+            on_mount: self.on_mount,
+            on_unmount: self.on_unmount,
         }
     }
 
@@ -221,8 +257,14 @@ where
             tag_name: self.tag_name,
             listeners: self.listeners,
             attributes: self.attributes,
+            properties: self.properties,
+            styles: self.styles,
+            classes: self.classes,
             children: self.children,
             namespace,
+            node_ref: self.node_ref,
+            on_mount: self.on_mount,
+            on_unmount: self.on_unmount,
         }
     }
 
@@ -265,6 +307,210 @@ where
         self
     }
 
+    /// Attach a `NodeRef` to this element.
+    ///
+    /// Once this element has been created in the physical DOM, the `NodeRef`
+    /// will refer to it, until the element is removed again.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump, NodeRef};
+    ///
+    /// let b = Bump::new();
+    /// let canvas_ref = NodeRef::new();
+    ///
+    /// let my_canvas = canvas(&b)
+    ///     .node_ref(&canvas_ref)
+    ///     .finish();
+    /// ```
+    #[inline]
+    pub fn node_ref(mut self, node_ref: &NodeRef) -> Self {
+        self.node_ref = Some(node_ref.id());
+        self
+    }
+
+    /// Set a callback to invoke after this element has been created in the
+    /// physical DOM.
+    ///
+    /// Like event listeners, the callback is given the virtual DOM's root
+    /// rendering component and a capability to schedule re-rendering. Mount
+    /// callbacks are invoked after the whole render's changes have been
+    /// applied to the physical DOM, so `NodeRef`s are already populated.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// let b = Bump::new();
+    ///
+    /// let clock = div(&b)
+    ///     .on_mount(|root, vdom| {
+    ///         // Start a timer that re-renders every second...
+    ///     })
+    ///     .finish();
+    /// ```
+    #[inline]
+    pub fn on_mount<F>(mut self, callback: F) -> Self
+    where
+        F: 'static + Fn(&mut dyn RootRender, VdomWeak),
+    {
+        self.on_mount = Some(LifecycleHook {
+            callback: self.bump.alloc(callback),
+        });
+        self
+    }
+
+    /// Set a callback to invoke before this element is removed from the
+    /// physical DOM.
+    ///
+    /// This is also invoked when the element is removed because one of its
+    /// ancestors is removed, or because the whole virtual DOM is unmounted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// let b = Bump::new();
+    ///
+    /// let clock = div(&b)
+    ///     .on_unmount(|root, vdom| {
+    ///         // Stop the timer...
+    ///     })
+    ///     .finish();
+    /// ```
+    #[inline]
+    pub fn on_unmount<F>(mut self, callback: F) -> Self
+    where
+        F: 'static + Fn(&mut dyn RootRender, VdomWeak),
+    {
+        self.on_unmount = Some(LifecycleHook {
+            callback: self.bump.alloc(callback),
+        });
+        self
+    }
+
+    /// Set a DOM property on this element.
+    ///
+    /// Properties are the live state of the physical DOM node, such as an
+    /// `<input>`'s current `value`, rather than the markup's attributes. The
+    /// user can change them behind our back, so each render compares the
+    /// property against the physical DOM node's live property, not the old
+    /// virtual DOM, and sets it if they differ. This is how to render
+    /// controlled inputs. When a property is no longer set, it is reset to the
+    /// empty value of its type: `""`, `false`, or `0`.
+    ///
+    /// Properties are not included in `render_to_string`'s markup.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// let b = Bump::new();
+    ///
+    /// // A checkbox that stays checked, no matter how often it is clicked.
+    /// let my_checkbox = input(&b)
+    ///     .attr("type", "checkbox")
+    ///     .prop("checked", true)
+    ///     .finish();
+    /// ```
+    #[inline]
+    pub fn prop<V>(mut self, name: &'a str, value: V) -> Self
+    where
+        V: Into<PropertyValue<'a>>,
+    {
+        self.properties.push(Property {
+            name,
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Add an inline style declaration to this element.
+    ///
+    /// Unlike setting the whole `style` attribute, each declaration is diffed
+    /// separately, and only the declarations that changed are updated on the
+    /// physical DOM node. `property` is the CSS property name, such as
+    /// `"background-color"`, not the camel-cased JavaScript name. Don't also
+    /// set the `style` attribute on an element with style declarations.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// let b = Bump::new();
+    ///
+    /// // Create `<div style="color: red; width: 10px;">`.
+    /// let my_div = div(&b)
+    ///     .style("color", "red")
+    ///     .style("width", "10px")
+    ///     .finish();
+    /// ```
+    #[inline]
+    pub fn style(mut self, property: &'a str, value: &'a str) -> Self {
+        self.styles.push(Style { property, value });
+        self
+    }
+
+    /// Add a class to this element.
+    ///
+    /// Unlike setting the whole `class` attribute, classes are diffed token by
+    /// token, and only the classes that were added or removed are updated in
+    /// the physical DOM node's `classList`.
+    ///
+    /// `classList` only accepts single class names, so `name` is split on
+    /// whitespace and each class in it is added separately. Empty names add no
+    /// classes.
+    ///
+    /// An element's classes and its `class` attribute would overwrite each
+    /// other when diffed, so don't also set the `class` attribute on an element
+    /// with classes. With the `debug-checks` feature enabled, doing so panics.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// let b = Bump::new();
+    ///
+    /// // Create `<li class="todo completed">`.
+    /// let my_li = li(&b).class("todo").class("completed").finish();
+    /// ```
+    #[inline]
+    pub fn class(mut self, name: &'a str) -> Self {
+        self.classes.extend(name.split_whitespace());
+        self
+    }
+
+    /// Add a class to this element if `should_add` is true.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// let b = Bump::new();
+    /// let completed = true;
+    ///
+    /// // Create `<li class="todo completed">`.
+    /// let my_li = li(&b)
+    ///     .class("todo")
+    ///     .class_if("completed", completed)
+    ///     .finish();
+    /// ```
+    #[inline]
+    pub fn class_if(self, name: &'a str, should_add: bool) -> Self {
+        if should_add {
+            self.class(name)
+        } else {
+            self
+        }
+    }
+
     /// Create the virtual DOM node described by this builder.
     ///
     /// # Example
@@ -291,14 +537,26 @@ where
         let attributes: &'a Attributes = self.bump.alloc(self.attributes);
         let attributes: &'a [Attribute<'a>] = attributes.as_ref();
 
-        Node::element(
+        let properties: &'a [Property<'a>] = self.properties.into_bump_slice();
+        let styles: &'a [Style<'a>] = self.styles.into_bump_slice();
+        let classes: &'a [&'a str] = self.classes.into_bump_slice();
+
+        Node::from_element(
             self.bump,
-            self.key,
-            self.tag_name,
-            listeners,
-            attributes,
-            children,
-            self.namespace,
+            ElementNode {
+                key: self.key,
+                tag_name: self.tag_name,
+                listeners,
+                attributes,
+                properties,
+                styles,
+                classes,
+                children,
+                namespace: self.namespace,
+                node_ref: self.node_ref,
+                on_mount: self.on_mount,
+                on_unmount: self.on_unmount,
+            },
         )
     }
 }
@@ -340,6 +598,43 @@ where
         });
         self
     }
+
+    /// Add a new event listener to this element that sends a message to the
+    /// root rendering component.
+    ///
+    /// When the `event` occurs, `callback` is invoked to create a message,
+    /// which is passed to the root rendering component's `Update::update`
+    /// method. If that returns `ShouldRender::Yes`, a re-render is scheduled.
+    ///
+    /// The root rendering component must be mounted with `Vdom::with_update`,
+    /// and its `Update::Msg` type must be `M`. Otherwise, the message is
+    /// dropped and an error is logged.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo::Bump};
+    ///
+    /// enum Msg {
+    ///     Clicked,
+    /// }
+    ///
+    /// let b = Bump::new();
+    ///
+    /// // A button that tells the root that it was clicked, without needing to
+    /// // know the root's type.
+    /// let my_button = button(&b).on_msg("click", |_event| Msg::Clicked).finish();
+    /// ```
+    #[inline]
+    pub fn on_msg<F, M>(self, event: &'a str, callback: F) -> Self
+    where
+        F: 'static + Fn(web_sys::Event) -> M,
+        M: 'static,
+    {
+        self.on(event, move |root, vdom, event| {
+            crate::update::send(root, &vdom, Box::new(callback(event)));
+        })
+    }
 }
 
 impl<'a, Listeners, Children>
@@ -350,6 +645,12 @@ where
 {
     /// Add a new attribute to this element.
     ///
+    /// The `value`, `checked` and `selected` attributes also keep the
+    /// corresponding property of the live DOM element in sync, so that they
+    /// reflect the rendered state even after the user has changed it. Other
+    /// attributes only set the markup's state; use `prop` for any other live
+    /// state.
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -1032,6 +1333,177 @@ pub fn text<'a>(contents: &'a str) -> Node<'a> {
     Node::text(contents)
 }
 
+/// Construct an empty node, which renders nothing.
+///
+/// Use this (or `Option<Node>`, which converts into a `Node` with `.into()`) to
+/// render a child only some of the time. The empty node's place among its
+/// siblings is held by a comment in the physical DOM, so the children around
+/// it keep their positions whether or not it is empty, and toggling it only
+/// replaces the one node. Empty nodes cannot be keyed, so filter optional
+/// children out of keyed lists instead; the `debug-checks` feature reports
+/// empty nodes among keyed siblings.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, Node, Render, RenderContext};
+///
+/// struct Notice {
+///     dismissed: bool,
+/// }
+///
+/// impl Render for Notice {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let banner = if self.dismissed {
+///             none()
+///         } else {
+///             p(&cx).children([text("New version available!")]).finish()
+///         };
+///         let footer = if self.dismissed {
+///             None
+///         } else {
+///             Some(button(&cx).children([text("Dismiss")]).finish())
+///         };
+///         div(&cx)
+///             .children([banner, text("Content"), footer.into()])
+///             .finish()
+///     }
+/// }
+/// ```
+#[inline]
+pub fn none<'a>() -> Node<'a> {
+    Node::empty()
+}
+
+/// Construct a raw HTML node: trusted markup that is rendered as the
+/// `innerHTML` of a `<span>` wrapper element.
+///
+/// Use `raw_html_in` to choose a different wrapper element, for example when
+/// the markup contains block-level elements, or is SVG.
+///
+/// The markup is **not** escaped or sanitized, so never pass it anything that
+/// came from an untrusted source. Event listeners cannot be attached to the
+/// nodes inside the markup. When diffing, the wrapper's `innerHTML` is only set
+/// again when the markup changes.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::builder::*;
+///
+/// let help = raw_html("<p>Press <kbd>Ctrl</kbd>+<kbd>S</kbd> to save.</p>");
+/// ```
+#[inline]
+pub fn raw_html<'a>(html: &'a str) -> Node<'a> {
+    Node::raw_html(RawHtmlNode::DEFAULT_TAG_NAME, None, html)
+}
+
+/// Construct a raw HTML node whose markup is rendered as the `innerHTML` of a
+/// wrapper element with the given tag name and namespace.
+///
+/// The markup is parsed in the wrapper's context, so SVG markup needs a wrapper
+/// in the SVG namespace, such as a `<g>`. See `raw_html` for the caveats of raw
+/// HTML.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::builder::*;
+///
+/// let help = raw_html_in("div", None, "<p>Press <kbd>?</kbd> for help.</p>");
+/// let icon = raw_html_in(
+///     "g",
+///     Some("http://www.w3.org/2000/svg"),
+///     "<circle cx=\"8\" cy=\"8\" r=\"4\"/>",
+/// );
+/// ```
+#[inline]
+pub fn raw_html_in<'a>(tag_name: &'a str, namespace: Option<&'a str>, html: &'a str) -> Node<'a> {
+    Node::raw_html(tag_name, namespace, html)
+}
+
+/// Construct a portal that renders `child` into the `target` container instead
+/// of into its parent.
+///
+/// This is useful for things like modal dialogs and tooltips, which need to be
+/// attached to `document.body` rather than nested deep inside the virtual
+/// DOM's container. The portal's child is still diffed, cached, and has its
+/// event listeners dispatched by the same `Vdom` as the rest of the tree. Its
+/// place inside its parent is held by a `<!--portal-->` comment.
+///
+/// The child may be a fragment, or a `Cached` component that renders one, in
+/// which case each of the fragment's children is rendered into the target, in
+/// order.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, Node, Render, RenderContext};
+///
+/// struct Modal {
+///     // The container to render the dialog into, e.g. `document.body`.
+///     layer: dodrio::Element,
+/// }
+///
+/// impl Render for Modal {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let dialog = div(&cx)
+///             .attr("class", "modal")
+///             .children([text("Are you sure?")])
+///             .finish();
+///         div(&cx)
+///             .children([text("Main content"), portal(&cx, &self.layer, dialog)])
+///             .finish()
+///     }
+/// }
+/// ```
+#[inline]
+pub fn portal<'a, B>(bump: B, target: &crate::Element, child: Node<'a>) -> Node<'a>
+where
+    B: Into<&'a Bump>,
+{
+    Node::portal(bump.into(), target, child)
+}
+
+/// Construct a fragment: a list of nodes that is rendered in place, as
+/// siblings of the fragment's own siblings, without an element to wrap them.
+///
+/// This lets a component render multiple root nodes. Fragments can be nested,
+/// and can be the root of a `Vdom` or of a `Cached` component, but cannot be a
+/// portal's child. When diffing, keyed fragment children are matched against
+/// their keyed siblings as if the fragment were not there.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, Node, Render, RenderContext};
+///
+/// struct Page;
+///
+/// impl Render for Page {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         fragment(
+///             &cx,
+///             [
+///                 header(&cx).children([text("Header")]).finish(),
+///                 section(&cx).children([text("Content")]).finish(),
+///                 footer(&cx).children([text("Footer")]).finish(),
+///             ],
+///         )
+///     }
+/// }
+/// ```
+#[inline]
+pub fn fragment<'a, B, Children>(bump: B, children: Children) -> Node<'a>
+where
+    B: Into<&'a Bump>,
+    Children: 'a + AsRef<[Node<'a>]>,
+{
+    let bump = bump.into();
+    let children: &'a Children = bump.alloc(children);
+    Node::fragment(bump, children.as_ref())
+}
+
 /// Construct an attribute for an element.
 ///
 /// # Example
@@ -1073,3 +1545,107 @@ where
         callback: bump.alloc(callback),
     }
 }
+
+/// Render a child, falling back to another node if rendering the child panics.
+///
+/// `render` is called to render the child. If it panics, the panic is caught,
+/// logged, and `fallback` is called to render a replacement node instead. The
+/// rest of the virtual DOM renders as usual, and the virtual DOM remains
+/// usable for subsequent renders.
+///
+/// Panics can only be caught when building with `panic = "unwind"`. In
+/// particular, `wasm32-unknown-unknown` builds abort on panic by default, in
+/// which case this is equivalent to just calling `render`.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, Node, Render, RenderContext};
+///
+/// struct Page<W> {
+///     widget: W,
+/// }
+///
+/// impl<W: Render> Render for Page<W> {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let widget = error_boundary(
+///             cx,
+///             |cx| self.widget.render(cx),
+///             |cx| p(&cx).children([text("This widget is broken.")]).finish(),
+///         );
+///         div(&cx).children([widget]).finish()
+///     }
+/// }
+/// ```
+pub fn error_boundary<'a, F, G>(cx: &mut RenderContext<'a>, render: F, fallback: G) -> Node<'a>
+where
+    F: FnOnce(&mut RenderContext<'a>) -> Node<'a>,
+    G: FnOnce(&mut RenderContext<'a>) -> Node<'a>,
+{
+    // Unwinding out of `render` cannot leave the rendering context in an
+    // inconsistent state: anything half-rendered is left in the bump arena and
+    // never referenced, and any borrows of the cached set are released.
+    match panic::catch_unwind(AssertUnwindSafe(|| render(cx))) {
+        Ok(node) => node,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<String>().map(|s| &s[..]))
+                .unwrap_or("Box<Any>");
+            error!("error_boundary: rendering panicked: {}", message);
+            fallback(cx)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use super::*;
+    use crate::headless::testing::*;
+    use crate::{Cached, Render};
+
+    struct Widget {
+        broken: bool,
+    }
+
+    impl Render for Widget {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            // Start rendering some children, including a cached one, before
+            // panicking halfway through.
+            let greeting = Cached::new(Greeting::default()).render(cx);
+            if self.broken {
+                panic!("widget is broken");
+            }
+            p(&cx).children([text("widget"), greeting]).finish()
+        }
+    }
+
+    struct Page {
+        broken: bool,
+    }
+
+    impl Render for Page {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let broken = self.broken;
+            let widget =
+                error_boundary(cx, |cx| Widget { broken }.render(cx), |_| text("fallback"));
+            div(&cx).children([widget]).finish()
+        }
+    }
+
+    #[test]
+    fn error_boundaries() {
+        let (container, vdom) = mount(Page { broken: true });
+        assert_eq!(container.inner_html(), "<div>fallback</div>");
+
+        // The vdom is still usable after a render panicked.
+        vdom.immediately_render_and_diff(Page { broken: false });
+        assert_eq!(
+            container.inner_html(),
+            "<div><p>widget<p>Hello, </p></p></div>"
+        );
+        vdom.immediately_render_and_diff(Page { broken: true });
+        assert_eq!(container.inner_html(), "<div>fallback</div>");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::signal::TrackingScope;
 use crate::{
     cached_set::CachedSet,
     node::{CachedNode, NodeKey},
@@ -5,28 +6,155 @@ use crate::{
 };
 use std::any::TypeId;
 use std::cell::Cell;
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wasm_bindgen::UnwrapThrowExt;
+
+static INSTANCE_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 /// A renderable that supports caching for when rendering is expensive but can
 /// generate the same DOM tree.
-#[derive(Clone, Debug, Default)]
-pub struct Cached<R>
+///
+/// Reading a `Signal` while rendering the inner `R` makes the cached rendering
+/// depend on it: writing the `Signal` invalidates the cache automatically.
+///
+/// The same `Cached<R>` can be rendered into multiple virtual DOMs, for example
+/// by sharing it with an `Rc`. Each virtual DOM keeps its own copy of the
+/// cached rendering, and reuses it until the `Cached<R>` is invalidated.
+///
+/// Re-renders are diffed against a *template*: a rendering of a prototype `R`
+/// whose physical DOM is built once and then cloned. `Cached::new` renders
+/// `R::default()` as the prototype, `Cached::with_template` renders
+/// `CachedTemplate::template()`, and `Cached::without_template` caches without
+/// a template.
+pub struct Cached<R> {
+    inner: R,
+
+    // Identifies this `Cached<R>` in the cached sets of the virtual DOMs that
+    // it is rendered into.
+    instance: CachedInstanceId,
+
+    // Bumped on invalidation. Each virtual DOM only reuses its cached
+    // rendering of this `Cached<R>` if it was rendered at the current
+    // generation. Shared with the `Signal`s that the rendering depends on, so
+    // that they can invalidate it.
+    generation: Generation,
+
+    // The template that this `Cached<R>`'s renders are diffed against, and how
+    // to construct the prototype that it is rendered from.
+    template: Option<(TemplateId, fn() -> R)>,
+}
+
+/// A component that provides the prototype for its `Cached` renders'
+/// templates.
+///
+/// Implement this trait for components that don't implement `Default`, or
+/// whose default value renders a different shape of DOM tree than they
+/// usually do, and cache them with `Cached::with_template`.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, bumpalo, Cached, CachedTemplate, Node, Render, RenderContext};
+///
+/// pub struct Avatar {
+///     user_id: u32,
+/// }
+///
+/// impl Render for Avatar {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let src = bumpalo::format!(in cx.bump, "/avatars/{}.png", self.user_id);
+///         img(&cx).attr("src", src.into_bump_str()).finish()
+///     }
+/// }
+///
+/// impl CachedTemplate for Avatar {
+///     fn template() -> Avatar {
+///         Avatar { user_id: 0 }
+///     }
+/// }
+///
+/// let avatar = Cached::with_template(Avatar { user_id: 42 });
+/// ```
+pub trait CachedTemplate {
+    /// Construct the prototype whose rendering is the template for every
+    /// `Cached<Self>` that is constructed with `Cached::with_template`.
+    fn template() -> Self;
+}
+
+impl<R> fmt::Debug for Cached<R>
 where
-    R: Default,
+    R: fmt::Debug,
 {
-    inner: R,
-    cached: Cell<Option<CachedNode>>,
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cached")
+            .field("inner", &self.inner)
+            .field("instance", &self.instance)
+            .field("generation", &self.generation)
+            .field("template", &self.template.map(|(id, _)| id))
+            .finish()
+    }
 }
 
 pub_unstable_internal! {
+    /// An identifier for a `Cached<R>` instance.
     #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
-    pub(crate) struct TemplateId(TypeId);
+    pub(crate) struct CachedInstanceId(u32);
 }
 
-impl<R> Cached<R>
+impl CachedInstanceId {
+    fn next() -> CachedInstanceId {
+        let next = INSTANCE_COUNTER.fetch_add(1, Ordering::AcqRel) as u32;
+        let next = if next == u32::MAX { None } else { Some(next) };
+        CachedInstanceId(next.expect_throw("INSTANCE_COUNTER overflowed"))
+    }
+}
+
+/// A `Cached<R>`'s generation counter.
+pub(crate) type Generation = Rc<Cell<u32>>;
+
+/// Invalidate a `Cached<R>` by bumping its generation counter.
+pub(crate) fn bump_generation(generation: &Cell<u32>) {
+    generation.set(generation.get().wrapping_add(1));
+}
+
+impl<R> Default for Cached<R>
 where
-    R: Default,
+    R: 'static + Default,
 {
+    fn default() -> Cached<R> {
+        Cached::new(R::default())
+    }
+}
+
+impl<R> Clone for Cached<R>
+where
+    R: Clone,
+{
+    fn clone(&self) -> Cached<R> {
+        // The clone is a new instance, or else invalidating it would also
+        // invalidate the original, and vice versa.
+        Cached::with_prototype(self.inner.clone(), self.template)
+    }
+}
+
+pub_unstable_internal! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+    pub(crate) struct TemplateId(TypeId, TemplateSource);
+}
+
+/// Where a template's prototype comes from. `R::default()` and
+/// `R::template()` may render different shapes, so they get separate
+/// templates.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+enum TemplateSource {
+    Default,
+    CachedTemplate,
+}
+
+impl<R> Cached<R> {
     /// Construct a new `Cached<R>` of an inner `R`.
     ///
     /// # Example
@@ -53,9 +181,46 @@ where
     /// let cached_counter = Cached::new(counter);
     /// ```
     #[inline]
-    pub fn new(inner: R) -> Cached<R> {
-        let cached = Cell::new(None);
-        Cached { inner, cached }
+    pub fn new(inner: R) -> Cached<R>
+    where
+        R: 'static + Default,
+    {
+        let template_id = TemplateId(TypeId::of::<R>(), TemplateSource::Default);
+        Cached::with_prototype(inner, Some((template_id, R::default)))
+    }
+
+    /// Construct a new `Cached<R>` of an inner `R`, whose renders are diffed
+    /// against a template rendered from `R::template()` instead of from
+    /// `R::default()`.
+    ///
+    /// See `CachedTemplate` for an example.
+    #[inline]
+    pub fn with_template(inner: R) -> Cached<R>
+    where
+        R: 'static + CachedTemplate,
+    {
+        let template_id = TemplateId(TypeId::of::<R>(), TemplateSource::CachedTemplate);
+        Cached::with_prototype(inner, Some((template_id, R::template)))
+    }
+
+    /// Construct a new `Cached<R>` of an inner `R`, whose renders are not
+    /// diffed against a template.
+    ///
+    /// Re-renders are still reused until the `Cached<R>` is invalidated, but
+    /// each one builds its physical DOM from scratch. This works for any `R`,
+    /// even one that has no sensible prototype value.
+    #[inline]
+    pub fn without_template(inner: R) -> Cached<R> {
+        Cached::with_prototype(inner, None)
+    }
+
+    fn with_prototype(inner: R, template: Option<(TemplateId, fn() -> R)>) -> Cached<R> {
+        Cached {
+            inner,
+            instance: CachedInstanceId::next(),
+            generation: Rc::new(Cell::new(0)),
+            template,
+        }
     }
 
     /// Invalidate the cached rendering.
@@ -98,7 +263,7 @@ where
     /// ```
     #[inline]
     pub fn invalidate(cached: &Self) {
-        cached.cached.set(None);
+        bump_generation(&cached.generation);
     }
 
     /// Convert a `Cached<R>` back into a plain `R`.
@@ -108,19 +273,7 @@ where
     }
 }
 
-impl<R> Cached<R>
-where
-    R: 'static + Default,
-{
-    pub(crate) fn template_id() -> TemplateId {
-        TemplateId(TypeId::of::<R>())
-    }
-}
-
-impl<R> Deref for Cached<R>
-where
-    R: Default,
-{
+impl<R> Deref for Cached<R> {
     type Target = R;
 
     fn deref(&self) -> &R {
@@ -128,10 +281,7 @@ where
     }
 }
 
-impl<R> DerefMut for Cached<R>
-where
-    R: Default,
-{
+impl<R> DerefMut for Cached<R> {
     fn deref_mut(&mut self) -> &mut R {
         &mut self.inner
     }
@@ -139,59 +289,135 @@ where
 
 impl<R> Render for Cached<R>
 where
-    R: 'static + Default + Render,
+    R: 'static + Render,
 {
     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
-        let template = cx.template::<R>();
-        let cached = match self.cached.get() {
-            // This does-the-cache-contain-this-id check is necessary because
-            // the same `Cached<R>` instance can be rendered into vdom A, which
-            // will save the results into A's cached set and yield id X. Then,
-            // it can be rendered *again* into a second vdom B, and B's cached
-            // set does not have the saved results for id X. If we didn't have
-            // this check, instead of a cache miss, we would have a panic.
-            //
-            // This scenario should basically never happen in the real world,
-            // however. If we ever find that it is worth sharing a cached render
-            // component between multiple vdoms, and want to avoid these
-            // "unnecessary" cache misses, we can do this:
-            //
-            // * Make each `Cached<R>` have an instance id and generation
-            //   counter.
-            //
-            // * On invalidation, bump the generation counter.
-            //
-            // * Add a generation member to the `CacheEntry`.
-            //
-            // * Each vdom maintains a map from `Cached<R>` instance id to
-            // `CacheEntry`s.
-            //
-            // * We only re-use the cached results if the `Cached<R>`'s
-            //   generation counter matches the entry in the vdom's cached set.
-            //
-            // This is all do-able but is a bit more book keeping than we really
-            // want to do unless it is well motivated.
-            Some(cached)
-                if {
-                    let cached_set = cx.cached_set.borrow();
-                    cached_set.contains(cached.id)
-                } =>
-            {
+        let generation = self.generation.get();
+        let hit = {
+            let cached_set = cx.cached_set.borrow();
+            cached_set
+                .lookup(self.instance, generation)
+                .map(|id| CachedNode {
+                    id,
+                    key: cached_set.get(id).0.key(),
+                })
+        };
+        let cached = match hit {
+            Some(cached) => {
+                cx.cached_set.borrow_mut().record_hit();
                 cached
             }
-            _ => {
+            None => {
+                let template = self
+                    .template
+                    .and_then(|(template_id, prototype)| cx.template(template_id, prototype));
                 let mut key = NodeKey::NONE;
                 let id = CachedSet::insert(cx, false, template, |nested_cx| {
+                    let _tracking = TrackingScope::cached(&self.generation);
                     let node = self.inner.render(nested_cx);
                     key = node.key();
                     node
                 });
-                let cached = CachedNode { id, key };
-                self.cached.set(Some(cached));
-                cached
+                let mut cached_set = cx.cached_set.borrow_mut();
+                cached_set.set_instance(self.instance, &self.generation, generation, id);
+                cached_set.record_miss();
+                CachedNode { id, key }
             }
         };
 
         cached.into()
     }
 }
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use super::Cached;
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Node, Render, RenderContext};
+    use std::rc::Rc;
+
+    #[test]
+    fn cached_templates() {
+        let (container, vdom) = mount(Div(vec![greeting("Alice"), greeting("Bob")]));
+        assert_eq!(
+            container.inner_html(),
+            "<div><p>Hello, Alice</p><p>Hello, Bob</p></div>"
+        );
+
+        // Listeners are not cloned along with templates, so they must have been
+        // added to each clone.
+        for p in container.first_child().unwrap().child_nodes() {
+            assert_eq!(p.event_listeners(), vec!["click".to_string()]);
+        }
+
+        vdom.immediately_render_and_diff(Div(vec![greeting("Carol")]));
+        assert_eq!(container.inner_html(), "<div><p>Hello, Carol</p></div>");
+    }
+
+    // Has no `Default`, so it can only be cached with an explicit template or
+    // without one.
+    struct Badge(u32);
+
+    impl Render for Badge {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let count = bumpalo::format!(in cx.bump, "{}", self.0);
+            span(&cx)
+                .attr("class", "badge")
+                .children([text(count.into_bump_str())])
+                .finish()
+        }
+    }
+
+    impl crate::CachedTemplate for Badge {
+        fn template() -> Badge {
+            Badge(0)
+        }
+    }
+
+    #[test]
+    fn cached_template_prototypes() {
+        let expected = r#"<div><span class="badge">1</span><span class="badge">2</span></div>"#;
+        for &templated in &[true, false] {
+            let badge = |count| {
+                if templated {
+                    Cached::with_template(Badge(count))
+                } else {
+                    Cached::without_template(Badge(count))
+                }
+            };
+
+            let (container, vdom) = mount(Div::<Cached<Badge>>(vec![]));
+            vdom.immediately_render_and_diff(Div(vec![badge(1), badge(2)]));
+            assert_eq!(container.inner_html(), expected);
+            assert_eq!(
+                instruction_count(&vdom, "save_template"),
+                templated as usize
+            );
+        }
+    }
+
+    #[test]
+    fn cached_renders_are_shared_between_vdoms() {
+        let greeting = Rc::new(greeting("Alice"));
+        let mounted: Vec<_> = (0..2).map(|_| mount(Div(vec![greeting.clone()]))).collect();
+
+        // Rendering the same `Cached` into each vdom in turn reuses each
+        // vdom's own cached rendering.
+        for _ in 0..2 {
+            for (_, vdom) in &mounted {
+                vdom.weak().render_sync().unwrap();
+                assert_eq!(cache_hits_and_misses(vdom), (1, 0));
+            }
+        }
+
+        // Invalidating it re-renders it once in each vdom.
+        Cached::invalidate(&greeting);
+        for (_, vdom) in &mounted {
+            vdom.weak().render_sync().unwrap();
+            assert_eq!(cache_hits_and_misses(vdom), (0, 1));
+        }
+        for (container, _) in &mounted {
+            assert_eq!(container.inner_html(), "<div><p>Hello, Alice</p></div>");
+        }
+    }
+}
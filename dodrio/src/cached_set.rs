@@ -1,10 +1,15 @@
 use crate::{
+    cached::{CachedInstanceId, Generation},
     events::EventsRegistry,
     node::{Node, NodeKind},
     render_context::RenderContext,
 };
 use bumpalo::Bump;
 use fxhash::{FxHashMap, FxHashSet};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::rc::{Rc, Weak};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::u32;
 use wasm_bindgen::prelude::*;
@@ -15,6 +20,24 @@ pub_unstable_internal! {
     #[derive(Debug, Default)]
     pub(crate) struct CachedSet {
         items: FxHashMap<CacheId, CacheEntry>,
+
+        // The latest entry for each `Cached<R>` instance that has been
+        // rendered into this set, and that instance's generation counter.
+        instances: FxHashMap<CachedInstanceId, (CacheId, Weak<Cell<u32>>)>,
+
+        // The most bytes that the set may hold while it keeps entries that are
+        // no longer reachable from the current roots around for reuse. `None`
+        // means unreachable entries are dropped right away.
+        budget: Option<usize>,
+
+        // Advanced by every garbage collection, to order the entries by when
+        // they were last reachable.
+        clock: u64,
+
+        // How many `Cached<R>` renders were reused and redone since the last
+        // call to `take_hit_counts`.
+        hits: usize,
+        misses: usize,
     }
 }
 
@@ -50,6 +73,58 @@ pub(crate) struct CacheEntry {
     // Whether this entry should never be garbage collected. Typically only
     // templates are pinned.
     pinned: bool,
+
+    // The generation of the `Cached<R>` instance that this entry is a
+    // rendering of. Only meaningful for entries in `CachedSet::instances`.
+    generation: u32,
+
+    // The number of bytes allocated in `bump`.
+    bytes: usize,
+
+    // The `clock` of the last garbage collection that found this entry
+    // reachable from the roots, or of the one before it was inserted.
+    last_used: u64,
+}
+
+/// Statistics about a `Vdom`'s cache of `Cached` renders.
+///
+/// Get these with `Vdom::cache_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub(crate) entries: usize,
+    pub(crate) pinned_entries: usize,
+    pub(crate) retained_entries: usize,
+    pub(crate) bytes: usize,
+    pub(crate) budget: Option<usize>,
+}
+
+impl CacheStats {
+    /// The total number of cached renders, including templates.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// The number of cached renders that are never evicted. These are the
+    /// templates that `Cached` renders are cloned from.
+    pub fn pinned_entries(&self) -> usize {
+        self.pinned_entries
+    }
+
+    /// The number of cached renders that are not used by the current render,
+    /// but are kept within the budget in case they are rendered again.
+    pub fn retained_entries(&self) -> usize {
+        self.retained_entries
+    }
+
+    /// The total number of bytes allocated by all cached renders.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The budget set with `Vdom::set_cache_budget`, if any.
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
 }
 
 impl From<CacheId> for u32 {
@@ -67,6 +142,9 @@ impl CachedSet {
     }
 
     pub(crate) fn gc(&mut self, registry: &mut EventsRegistry, roots: FxHashSet<CacheId>) {
+        self.clock += 1;
+        let clock = self.clock;
+
         let mut marked = FxHashSet::default();
         marked.reserve(self.items.len());
 
@@ -83,14 +161,111 @@ impl CachedSet {
             }
         }
 
+        for id in &marked {
+            if let Some(entry) = self.items.get_mut(id) {
+                entry.last_used = clock;
+            }
+        }
+
+        if let Some(budget) = self.budget {
+            self.retain_within_budget(&mut marked, budget);
+        }
+
         self.items.retain(|id, entry| {
-            let keep = entry.pinned || marked.contains(id);
-            if !keep {
+            // Entries that were reachable as of the last collection (or that
+            // were inserted since) just left the physical DOM, so their
+            // listeners can no longer be invoked. Entries that were already
+            // unreachable had their listeners removed back then.
+            if !entry.pinned && entry.last_used + 1 == clock {
                 let node: &Node = unsafe { &*entry.node };
                 registry.remove_subtree(node);
             }
-            keep
+            entry.pinned || marked.contains(id)
         });
+
+        let items = &self.items;
+        self.instances.retain(|_, (id, _)| items.contains_key(id));
+    }
+
+    // Mark the unreachable entries that are worth keeping around for reuse,
+    // most recently used first, for as long as the set stays within budget.
+    //
+    // An entry is worth keeping if it is the latest rendering of a live
+    // `Cached<R>` instance, and that instance has not been invalidated since.
+    // Keeping an entry means keeping all the entries it references too.
+    fn retain_within_budget(&self, marked: &mut FxHashSet<CacheId>, budget: usize) {
+        let mut bytes: usize = self
+            .items
+            .iter()
+            .filter(|&(id, entry)| entry.pinned || marked.contains(id))
+            .map(|(_, entry)| entry.bytes)
+            .sum();
+
+        let mut candidates: Vec<CacheId> = self
+            .instances
+            .values()
+            .filter(|(id, generation)| {
+                !marked.contains(id)
+                    && match (self.items.get(id), generation.upgrade()) {
+                        (Some(entry), Some(generation)) => entry.generation == generation.get(),
+                        _ => false,
+                    }
+            })
+            .map(|&(id, _)| id)
+            .collect();
+        candidates.sort_by_key(|id| Reverse(self.items[id].last_used));
+
+        for id in candidates {
+            if marked.contains(&id) {
+                continue;
+            }
+            let entry = &self.items[&id];
+            let added: usize = Some(&id)
+                .into_iter()
+                .chain(entry.edges.iter())
+                .filter(|id| !marked.contains(id))
+                .map(|id| self.items[id].bytes)
+                .sum();
+            if bytes + added > budget {
+                // Evict this entry, and all the ones that were used less
+                // recently than it.
+                break;
+            }
+            bytes += added;
+            marked.insert(id);
+            marked.extend(entry.edges.iter().cloned());
+        }
+    }
+
+    /// Allow the given entry to be garbage collected again.
+    pub(crate) fn unpin(&mut self, id: CacheId) {
+        if let Some(entry) = self.items.get_mut(&id) {
+            entry.pinned = false;
+        }
+    }
+
+    /// Set the most bytes that the set may hold while keeping unreachable
+    /// entries around for reuse, or `None` to drop them right away.
+    pub(crate) fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    /// Get statistics about the entries in this set.
+    pub(crate) fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            entries: self.items.len(),
+            budget: self.budget,
+            ..CacheStats::default()
+        };
+        for entry in self.items.values() {
+            stats.bytes += entry.bytes;
+            if entry.pinned {
+                stats.pinned_entries += 1;
+            } else if entry.last_used != self.clock {
+                stats.retained_entries += 1;
+            }
+        }
+        stats
     }
 
     // Trace all the transitive edges to other cached entries that the given
@@ -103,7 +278,7 @@ impl CachedSet {
 
     fn trace_recursive(&self, edges: &mut FxHashSet<CacheId>, node: &Node) {
         match &node.kind {
-            NodeKind::Text(_) => return,
+            NodeKind::Text(_) | NodeKind::RawHtml(_) | NodeKind::Empty => return,
             NodeKind::Cached(c) => {
                 debug_assert!(self.items.contains_key(&c.id));
                 edges.insert(c.id);
@@ -121,6 +296,63 @@ impl CachedSet {
                     self.trace_recursive(edges, child);
                 }
             }
+            NodeKind::Portal(portal) => {
+                self.trace_recursive(edges, &portal.child);
+            }
+            NodeKind::Fragment(fragment) => {
+                for child in fragment.children {
+                    self.trace_recursive(edges, child);
+                }
+            }
+        }
+    }
+
+    /// Is the given node a fragment, or a cached fragment?
+    pub(crate) fn is_fragment(&self, node: &Node) -> bool {
+        match node.kind {
+            NodeKind::Fragment(_) => true,
+            NodeKind::Cached(ref c) => self.is_fragment(self.get(c.id).0),
+            _ => false,
+        }
+    }
+
+    /// Splice any fragments (including cached fragments) in the given list of
+    /// siblings into the list, so that each of the resulting nodes is rendered
+    /// as exactly one physical DOM node.
+    ///
+    /// `cached_fragment` is called with the id of every cached fragment that
+    /// is spliced away.
+    pub(crate) fn flatten<'b>(
+        &'b self,
+        nodes: &'b [Node<'b>],
+        cached_fragment: &mut dyn FnMut(CacheId),
+    ) -> Cow<'b, [Node<'b>]> {
+        if !nodes.iter().any(|node| self.is_fragment(node)) {
+            return Cow::Borrowed(nodes);
+        }
+        let mut flat = Vec::with_capacity(nodes.len());
+        self.flatten_into(&mut flat, nodes, cached_fragment);
+        Cow::Owned(flat)
+    }
+
+    fn flatten_into<'b>(
+        &'b self,
+        flat: &mut Vec<Node<'b>>,
+        nodes: &'b [Node<'b>],
+        cached_fragment: &mut dyn FnMut(CacheId),
+    ) {
+        for node in nodes {
+            match node.kind {
+                NodeKind::Fragment(fragment) => {
+                    self.flatten_into(flat, fragment.children, cached_fragment);
+                }
+                NodeKind::Cached(ref c) if self.is_fragment(node) => {
+                    cached_fragment(c.id);
+                    let (node, _) = self.get(c.id);
+                    self.flatten_into(flat, std::slice::from_ref(node), cached_fragment);
+                }
+                _ => flat.push(node.clone()),
+            }
         }
     }
 
@@ -140,9 +372,10 @@ impl CachedSet {
         F: for<'a> FnOnce(&mut RenderContext<'a>) -> Node<'a>,
     {
         let set = cx.cached_set;
-        let bump = Bump::new();
+        let mut bump = Bump::new();
         let (node, edges) = {
             let mut nested_cx = RenderContext::new(&bump, cx.cached_set, cx.templates);
+            nested_cx.context = cx.context.clone();
             let node = f(&mut nested_cx);
             let node = bump.alloc(node);
             let edges = {
@@ -155,23 +388,77 @@ impl CachedSet {
             )
         };
 
+        let mut bytes = 0;
+        unsafe {
+            // Safety relies on only reading each chunk's length, and never its
+            // (possibly uninitialized) contents.
+            bump.each_allocated_chunk(|chunk| bytes += chunk.len());
+        }
+
+        let mut set = set.borrow_mut();
         let entry = CacheEntry {
             bump,
             node,
             edges,
             template,
             pinned,
+            generation: 0,
+            bytes,
+            last_used: set.clock,
         };
 
-        let mut set = set.borrow_mut();
         let id = set.next_id();
         set.items.insert(id, entry);
         id
     }
 
-    /// Does the cached set contain a cached node with the given id?
-    pub fn contains(&self, id: CacheId) -> bool {
-        self.items.contains_key(&id)
+    /// Get the entry for the given `Cached<R>` instance, if it was rendered at
+    /// the given generation.
+    pub(crate) fn lookup(&self, instance: CachedInstanceId, generation: u32) -> Option<CacheId> {
+        let (id, _) = *self.instances.get(&instance)?;
+        let entry = self.items.get(&id)?;
+        if entry.generation == generation {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Make the given entry the latest rendering of the given `Cached<R>`
+    /// instance, which was rendered when its generation counter was at
+    /// `rendered_at`.
+    pub(crate) fn set_instance(
+        &mut self,
+        instance: CachedInstanceId,
+        generation: &Generation,
+        rendered_at: u32,
+        id: CacheId,
+    ) {
+        self.items
+            .get_mut(&id)
+            .expect_throw("CachedSet::set_instance: should have id in set")
+            .generation = rendered_at;
+        self.instances
+            .insert(instance, (id, Rc::downgrade(generation)));
+    }
+
+    /// Record that a `Cached<R>` reused its cached render.
+    pub(crate) fn record_hit(&mut self) {
+        self.hits += 1;
+    }
+
+    /// Record that a `Cached<R>` had to be re-rendered.
+    pub(crate) fn record_miss(&mut self) {
+        self.misses += 1;
+    }
+
+    /// Get the number of cache hits and misses since the last call, and reset
+    /// the counts.
+    pub(crate) fn take_hit_counts(&mut self) -> (usize, usize) {
+        let counts = (self.hits, self.misses);
+        self.hits = 0;
+        self.misses = 0;
+        counts
     }
 
     /// Get the cached node and its template (if any) for the given cache id.
@@ -184,3 +471,45 @@ impl CachedSet {
         (node, entry.template)
     }
 }
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::testing::*;
+    use crate::{builder::*, Cached, Node, Render, RenderContext};
+    use std::rc::Rc;
+
+    // Shows one of the shared `Cached` views at a time.
+    struct Views(Rc<Vec<Cached<Greeting>>>, usize);
+
+    impl Render for Views {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            div(&cx).children([self.0[self.1].render(cx)]).finish()
+        }
+    }
+
+    #[test]
+    fn cache_budget_retains_hidden_cached_renders() {
+        let views = Rc::new(vec![greeting("Alice"), greeting("Bob")]);
+
+        for &(budget, retained) in &[(None, false), (Some(1 << 20), true), (Some(0), false)] {
+            let (container, vdom) = mount(Views(views.clone(), 0));
+            vdom.set_cache_budget(budget);
+
+            vdom.immediately_render_and_diff(Views(views.clone(), 1));
+            let stats = vdom.cache_stats();
+            assert_eq!(stats.budget(), budget);
+            assert_eq!(stats.retained_entries(), retained as usize);
+            assert!(stats.bytes() > 0);
+
+            // Showing the first view again only re-renders it if it was
+            // evicted.
+            vdom.immediately_render_and_diff(Views(views.clone(), 0));
+            let expected = if retained { (1, 0) } else { (0, 1) };
+            assert_eq!(cache_hits_and_misses(&vdom), expected);
+            assert_eq!(container.inner_html(), "<div><p>Hello, Alice</p></div>");
+
+            let p = container.first_child().unwrap().first_child().unwrap();
+            assert_eq!(p.event_listeners(), vec!["click".to_string()]);
+        }
+    }
+}
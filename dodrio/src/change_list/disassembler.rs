@@ -0,0 +1,539 @@
+//! Decoding change list instruction streams back into structured instructions.
+//!
+//! This is the inverse of `InstructionEmitter`: it turns the raw `u32`s that
+//! the emitter encodes into a `Vec<Instruction>`, and renders them as a
+//! human-readable listing. It is intended for debugging and testing the
+//! change lists that diffing produces.
+
+use super::emitter::InstructionEmitter;
+use crate::OwnedPropertyValue;
+use fxhash::FxHashMap;
+use std::fmt;
+
+/// A single, decoded change list instruction.
+///
+/// Each variant corresponds to one of `InstructionEmitter`'s opcodes. Strings
+/// that are passed by pointer and length (rather than through the strings
+/// cache) are copied out of the instruction stream, since the pointers are
+/// meaningless once the change list has been applied. Cached strings are
+/// referenced by their key; use a `Disassembly` to resolve them to their text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Instruction {
+    /// Set the text content of the node on top of the stack.
+    SetText { text: String },
+
+    /// Pop a node and remove it along with all its next siblings.
+    RemoveSelfAndNextSiblings,
+
+    /// Pop the new node and the old node, replace the old node with the new
+    /// one, and push the new node.
+    ReplaceWith,
+
+    /// Set an attribute on the node on top of the stack.
+    SetAttribute { name_key: u32, value_key: u32 },
+
+    /// Remove an attribute from the node on top of the stack.
+    RemoveAttribute { name_key: u32 },
+
+    /// Push the top node's `n`th child, counting from the end.
+    PushReverseChild { n: u32 },
+
+    /// Pop a node, and push the new top node's `n`th child.
+    PopPushChild { n: u32 },
+
+    /// Pop a node.
+    Pop,
+
+    /// Pop a node and append it to the new top node's children.
+    AppendChild,
+
+    /// Create a new text node and push it.
+    CreateTextNode { text: String },
+
+    /// Create a new element and push it.
+    CreateElement { tag_name_key: u32 },
+
+    /// Add an event listener to the node on top of the stack.
+    NewEventListener { event_key: u32, a: u32, b: u32 },
+
+    /// Update the callback for an existing event listener on the node on top
+    /// of the stack.
+    UpdateEventListener { event_key: u32, a: u32, b: u32 },
+
+    /// Remove an event listener from the node on top of the stack.
+    RemoveEventListener { event_key: u32 },
+
+    /// Add a string to the strings cache with the given key.
+    AddCachedString { string: String, key: u32 },
+
+    /// Remove the string with the given key from the strings cache.
+    DropCachedString { key: u32 },
+
+    /// Create a new element in the given namespace and push it.
+    CreateElementNs {
+        tag_name_key: u32,
+        namespace_key: u32,
+    },
+
+    /// Save the top node's children in `start..end` to the temporaries
+    /// starting at `temp_base`.
+    SaveChildrenToTemporaries {
+        temp_base: u32,
+        start: u32,
+        end: u32,
+    },
+
+    /// Push the top node's `n`th child.
+    PushChild { n: u32 },
+
+    /// Push a saved temporary.
+    PushTemporary { temp: u32 },
+
+    /// Pop the node to insert and the reference node, insert the former
+    /// before the latter, and push the inserted node.
+    InsertBefore,
+
+    /// Pop a node, and push the new top node's `n`th child, counting from the
+    /// end.
+    PopPushReverseChild { n: u32 },
+
+    /// Remove the top node's `n`th child.
+    RemoveChild { n: u32 },
+
+    /// Set the class of the node on top of the stack.
+    SetClass { class_key: u32 },
+
+    /// Save a deep clone of the node on top of the stack as a template.
+    SaveTemplate { id: u32 },
+
+    /// Push a deep clone of a saved template.
+    PushTemplate { id: u32 },
+
+    /// Create a new comment node and push it.
+    CreateComment { text: String },
+
+    /// Push the `index`th child of the portal whose placeholder is on top of
+    /// the stack.
+    PushPortalChild { index: u32 },
+
+    /// Pop a portal's child, make sure it is in the given target container,
+    /// and associate it with the placeholder on top of the stack as its
+    /// `index`th child.
+    MountPortal { target_id: u32, index: u32 },
+
+    /// Attach the node ref with the given id to the node on top of the stack.
+    SetNodeRef { id: u32 },
+
+    /// Detach the node ref with the given id, if it is attached to the node on
+    /// top of the stack.
+    RemoveNodeRef { id: u32 },
+
+    /// Set the inner HTML of the element on top of the stack.
+    SetInnerHtml { html: String },
+
+    /// Set a property on the node on top of the stack, if it differs from the
+    /// node's live property.
+    SetProperty {
+        name_key: u32,
+        value: OwnedPropertyValue,
+    },
+
+    /// Reset a property on the node on top of the stack.
+    RemoveProperty { name_key: u32 },
+
+    /// Set an inline style property on the node on top of the stack.
+    SetStyleProperty { name_key: u32, value_key: u32 },
+
+    /// Remove an inline style property from the node on top of the stack.
+    RemoveStyleProperty { name_key: u32 },
+
+    /// Add a class to the node on top of the stack.
+    AddClass { class_key: u32 },
+
+    /// Remove a class from the node on top of the stack.
+    RemoveClass { class_key: u32 },
+
+    /// Remove the children of the portal whose placeholder is on top of the
+    /// stack from the `len`th onwards.
+    TruncatePortal { len: u32 },
+}
+
+impl Instruction {
+    /// Get the name of this instruction's opcode, as it appears in
+    /// `InstructionEmitter` and the JS interpreter.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::SetText { .. } => "set_text",
+            Instruction::RemoveSelfAndNextSiblings => "remove_self_and_next_siblings",
+            Instruction::ReplaceWith => "replace_with",
+            Instruction::SetAttribute { .. } => "set_attribute",
+            Instruction::RemoveAttribute { .. } => "remove_attribute",
+            Instruction::PushReverseChild { .. } => "push_reverse_child",
+            Instruction::PopPushChild { .. } => "pop_push_child",
+            Instruction::Pop => "pop",
+            Instruction::AppendChild => "append_child",
+            Instruction::CreateTextNode { .. } => "create_text_node",
+            Instruction::CreateElement { .. } => "create_element",
+            Instruction::NewEventListener { .. } => "new_event_listener",
+            Instruction::UpdateEventListener { .. } => "update_event_listener",
+            Instruction::RemoveEventListener { .. } => "remove_event_listener",
+            Instruction::AddCachedString { .. } => "add_cached_string",
+            Instruction::DropCachedString { .. } => "drop_cached_string",
+            Instruction::CreateElementNs { .. } => "create_element_ns",
+            Instruction::SaveChildrenToTemporaries { .. } => "save_children_to_temporaries",
+            Instruction::PushChild { .. } => "push_child",
+            Instruction::PushTemporary { .. } => "push_temporary",
+            Instruction::InsertBefore => "insert_before",
+            Instruction::PopPushReverseChild { .. } => "pop_push_reverse_child",
+            Instruction::RemoveChild { .. } => "remove_child",
+            Instruction::SetClass { .. } => "set_class",
+            Instruction::SaveTemplate { .. } => "save_template",
+            Instruction::PushTemplate { .. } => "push_template",
+            Instruction::CreateComment { .. } => "create_comment",
+            Instruction::PushPortalChild { .. } => "push_portal_child",
+            Instruction::MountPortal { .. } => "mount_portal",
+            Instruction::SetNodeRef { .. } => "set_node_ref",
+            Instruction::RemoveNodeRef { .. } => "remove_node_ref",
+            Instruction::SetInnerHtml { .. } => "set_inner_html",
+            Instruction::SetProperty { .. } => "set_property",
+            Instruction::RemoveProperty { .. } => "remove_property",
+            Instruction::SetStyleProperty { .. } => "set_style_property",
+            Instruction::RemoveStyleProperty { .. } => "remove_style_property",
+            Instruction::AddClass { .. } => "add_class",
+            Instruction::RemoveClass { .. } => "remove_class",
+            Instruction::TruncatePortal { .. } => "truncate_portal",
+        }
+    }
+}
+
+/// Decode the given instruction stream, which was emitted by `emitter` since
+/// its last reset.
+///
+/// Panics if the stream is malformed, i.e. it contains an unknown opcode or an
+/// instruction is missing immediates.
+pub(crate) fn decode(instructions: &[u32], emitter: &InstructionEmitter) -> Vec<Instruction> {
+    let mut immediates = instructions.iter().cloned();
+    let mut decoded = vec![];
+
+    while let Some(op) = immediates.next() {
+        let mut next = || {
+            immediates
+                .next()
+                .expect("change list instruction is missing an immediate")
+        };
+        decoded.push(match op {
+            0 => Instruction::SetText {
+                text: read_string(emitter, &mut next),
+            },
+            1 => Instruction::RemoveSelfAndNextSiblings,
+            2 => Instruction::ReplaceWith,
+            3 => Instruction::SetAttribute {
+                name_key: next(),
+                value_key: next(),
+            },
+            4 => Instruction::RemoveAttribute { name_key: next() },
+            5 => Instruction::PushReverseChild { n: next() },
+            6 => Instruction::PopPushChild { n: next() },
+            7 => Instruction::Pop,
+            8 => Instruction::AppendChild,
+            9 => Instruction::CreateTextNode {
+                text: read_string(emitter, &mut next),
+            },
+            10 => Instruction::CreateElement {
+                tag_name_key: next(),
+            },
+            11 => Instruction::NewEventListener {
+                event_key: next(),
+                a: next(),
+                b: next(),
+            },
+            12 => Instruction::UpdateEventListener {
+                event_key: next(),
+                a: next(),
+                b: next(),
+            },
+            13 => Instruction::RemoveEventListener { event_key: next() },
+            14 => Instruction::AddCachedString {
+                string: read_string(emitter, &mut next),
+                key: next(),
+            },
+            15 => Instruction::DropCachedString { key: next() },
+            16 => Instruction::CreateElementNs {
+                tag_name_key: next(),
+                namespace_key: next(),
+            },
+            17 => Instruction::SaveChildrenToTemporaries {
+                temp_base: next(),
+                start: next(),
+                end: next(),
+            },
+            18 => Instruction::PushChild { n: next() },
+            19 => Instruction::PushTemporary { temp: next() },
+            20 => Instruction::InsertBefore,
+            21 => Instruction::PopPushReverseChild { n: next() },
+            22 => Instruction::RemoveChild { n: next() },
+            23 => Instruction::SetClass { class_key: next() },
+            24 => Instruction::SaveTemplate { id: next() },
+            25 => Instruction::PushTemplate { id: next() },
+            26 => Instruction::CreateComment {
+                text: read_string(emitter, &mut next),
+            },
+            27 => Instruction::PushPortalChild { index: next() },
+            28 => Instruction::MountPortal {
+                target_id: next(),
+                index: next(),
+            },
+            29 => Instruction::SetNodeRef { id: next() },
+            30 => Instruction::RemoveNodeRef { id: next() },
+            31 => Instruction::SetInnerHtml {
+                html: read_string(emitter, &mut next),
+            },
+            32 => {
+                let name_key = next();
+                let value = match next() {
+                    0 => OwnedPropertyValue::String(read_string(emitter, &mut next)),
+                    1 => {
+                        let (a, _) = (next(), next());
+                        OwnedPropertyValue::Bool(a != 0)
+                    }
+                    _ => {
+                        let (a, b) = (next(), next());
+                        OwnedPropertyValue::Number(f64::from_bits(
+                            u64::from(a) | u64::from(b) << 32,
+                        ))
+                    }
+                };
+                Instruction::SetProperty { name_key, value }
+            }
+            33 => Instruction::RemoveProperty { name_key: next() },
+            34 => Instruction::SetStyleProperty {
+                name_key: next(),
+                value_key: next(),
+            },
+            35 => Instruction::RemoveStyleProperty { name_key: next() },
+            36 => Instruction::AddClass { class_key: next() },
+            37 => Instruction::RemoveClass { class_key: next() },
+            38 => Instruction::TruncatePortal { len: next() },
+            op => panic!("unknown change list opcode: {}", op),
+        });
+    }
+
+    decoded
+}
+
+fn read_string<F>(emitter: &InstructionEmitter, next: &mut F) -> String
+where
+    F: FnMut() -> u32,
+{
+    let (pointer, length) = (next(), next());
+    // Safety: `emitter` emitted these immediates, and has not been reset
+    // since, so the string they reference is still alive.
+    unsafe { emitter.read_string(pointer, length).to_string() }
+}
+
+/// A decoded change list, along with the cached strings it references.
+///
+/// The `Display` implementation renders a human-readable listing of the
+/// instructions, with cached string keys resolved to their text:
+///
+/// ```text
+///    0: add_cached_string "li" -> #3
+///    1: push_child 1
+///    2: create_element #3 "li"
+///    3: create_text_node "item 2"
+///    4: append_child
+///    5: append_child
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Disassembly {
+    instructions: Vec<Instruction>,
+    strings: FxHashMap<u32, String>,
+}
+
+impl Disassembly {
+    /// Construct a new disassembly of `instructions`, given a snapshot of the
+    /// strings cache from before the instructions were applied.
+    pub(crate) fn new(
+        instructions: Vec<Instruction>,
+        mut strings: FxHashMap<u32, String>,
+    ) -> Disassembly {
+        // The snapshot doesn't include the strings that these instructions
+        // themselves add to the cache.
+        for instruction in &instructions {
+            if let Instruction::AddCachedString { string, key } = instruction {
+                strings.entry(*key).or_insert_with(|| string.clone());
+            }
+        }
+
+        Disassembly {
+            instructions,
+            strings,
+        }
+    }
+
+    /// Get the decoded instructions.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Resolve a cached string key to its text, if it is known.
+    pub fn string(&self, key: u32) -> Option<&str> {
+        self.strings.get(&key).map(|s| &s[..])
+    }
+
+    fn write_key(&self, f: &mut fmt::Formatter, key: u32) -> fmt::Result {
+        match self.string(key) {
+            Some(s) => write!(f, " #{} {:?}", key, s),
+            None => write!(f, " #{} <unknown>", key),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            write!(f, "{:4}: {}", i, instruction.name())?;
+            match *instruction {
+                Instruction::SetText { ref text }
+                | Instruction::CreateTextNode { ref text }
+                | Instruction::CreateComment { ref text }
+                | Instruction::SetInnerHtml { html: ref text } => write!(f, " {:?}", text)?,
+                Instruction::AddCachedString { ref string, key } => {
+                    write!(f, " {:?} -> #{}", string, key)?
+                }
+                Instruction::SetAttribute {
+                    name_key,
+                    value_key,
+                }
+                | Instruction::SetStyleProperty {
+                    name_key,
+                    value_key,
+                } => {
+                    self.write_key(f, name_key)?;
+                    self.write_key(f, value_key)?;
+                }
+                Instruction::SetProperty {
+                    name_key,
+                    ref value,
+                } => {
+                    self.write_key(f, name_key)?;
+                    write!(f, " {}", value)?;
+                }
+                Instruction::CreateElementNs {
+                    tag_name_key,
+                    namespace_key,
+                } => {
+                    self.write_key(f, tag_name_key)?;
+                    self.write_key(f, namespace_key)?;
+                }
+                Instruction::RemoveAttribute { name_key: key }
+                | Instruction::RemoveProperty { name_key: key }
+                | Instruction::RemoveStyleProperty { name_key: key }
+                | Instruction::CreateElement { tag_name_key: key }
+                | Instruction::RemoveEventListener { event_key: key }
+                | Instruction::DropCachedString { key }
+                | Instruction::SetClass { class_key: key }
+                | Instruction::AddClass { class_key: key }
+                | Instruction::RemoveClass { class_key: key } => self.write_key(f, key)?,
+                Instruction::NewEventListener { event_key, a, b }
+                | Instruction::UpdateEventListener { event_key, a, b } => {
+                    self.write_key(f, event_key)?;
+                    write!(f, " ({:#x}, {:#x})", a, b)?;
+                }
+                Instruction::SaveChildrenToTemporaries {
+                    temp_base,
+                    start,
+                    end,
+                } => write!(f, " {}..{} -> {}", start, end, temp_base)?,
+                Instruction::PushReverseChild { n }
+                | Instruction::PopPushChild { n }
+                | Instruction::PushChild { n }
+                | Instruction::PopPushReverseChild { n }
+                | Instruction::RemoveChild { n } => write!(f, " {}", n)?,
+                Instruction::PushPortalChild { index } => write!(f, " {}", index)?,
+                Instruction::TruncatePortal { len } => write!(f, " {}", len)?,
+                Instruction::PushTemporary { temp } => write!(f, " {}", temp)?,
+                Instruction::SaveTemplate { id }
+                | Instruction::PushTemplate { id }
+                | Instruction::SetNodeRef { id }
+                | Instruction::RemoveNodeRef { id } => write!(f, " {}", id)?,
+                Instruction::MountPortal { target_id, index } => {
+                    write!(f, " {} {}", target_id, index)?
+                }
+                Instruction::RemoveSelfAndNextSiblings
+                | Instruction::ReplaceWith
+                | Instruction::Pop
+                | Instruction::AppendChild
+                | Instruction::InsertBefore => {}
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "xxx-unstable-internal-use-only",
+    not(target_arch = "wasm32")
+))]
+mod tests {
+    use super::Instruction;
+    use crate::{builder::*, headless::Element, Node, Render, RenderContext, Vdom};
+
+    struct List(&'static [&'static str]);
+
+    impl Render for List {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let items = self.0.iter().map(|item| {
+                li(&cx)
+                    .attr("class", "item")
+                    .children([text(item)])
+                    .finish()
+            });
+            let items = bumpalo::collections::Vec::from_iter_in(items, cx.bump);
+            ul(&cx).children(items).finish()
+        }
+    }
+
+    #[test]
+    fn disassemble_change_list() {
+        let container = Element::create_element("div");
+        let vdom = Vdom::new(&container, List(&["a"]));
+        assert!(vdom.last_change_list().is_none());
+
+        vdom.record_change_lists(true);
+        vdom.immediately_render_and_diff(List(&["a", "b"]));
+        let change_list = vdom.last_change_list().unwrap();
+
+        assert_eq!(
+            change_list.instructions(),
+            &[
+                // The root is diffed as the container's only child.
+                Instruction::PushChild { n: 0 },
+                Instruction::CreateElement { tag_name_key: 1 },
+                Instruction::SetClass { class_key: 2 },
+                Instruction::SetText {
+                    text: "b".to_string()
+                },
+                Instruction::AppendChild,
+                // "ul" was not used by this render.
+                Instruction::DropCachedString { key: 0 },
+            ][..]
+        );
+
+        assert_eq!(
+            change_list.to_string(),
+            "   0: push_child 0\n\
+             \x20  1: create_element #1 \"li\"\n\
+             \x20  2: set_class #2 \"item\"\n\
+             \x20  3: set_text \"b\"\n\
+             \x20  4: append_child\n\
+             \x20  5: drop_cached_string #0 \"ul\"\n"
+        );
+
+        vdom.record_change_lists(false);
+        assert!(vdom.last_change_list().is_none());
+    }
+}
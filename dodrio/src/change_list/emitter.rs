@@ -19,18 +19,68 @@ use bumpalo::Bump;
 #[derive(Debug)]
 pub(crate) struct InstructionEmitter {
     bump: Bump,
+
+    // When pointers don't fit in a `u32` immediate, strings are referenced by
+    // their index in this table instead of by their address.
+    #[cfg(not(target_pointer_width = "32"))]
+    strings: std::cell::RefCell<Vec<(*const u8, usize)>>,
 }
 
 impl InstructionEmitter {
     /// Construct a new `InstructionEmitter` with its own bump arena.
     pub fn new() -> InstructionEmitter {
         let bump = Bump::new();
-        InstructionEmitter { bump }
+        InstructionEmitter {
+            bump,
+            #[cfg(not(target_pointer_width = "32"))]
+            strings: Default::default(),
+        }
+    }
+
+    /// Get the `(pointer, length)` immediates that refer to the given string.
+    ///
+    /// The string must outlive the instructions that reference it, which holds
+    /// for the strings in the virtual DOMs being diffed.
+    #[cfg(target_pointer_width = "32")]
+    #[inline]
+    pub fn string_immediates(&self, string: &str) -> (u32, u32) {
+        (string.as_ptr() as u32, string.len() as u32)
+    }
+
+    /// Get the `(pointer, length)` immediates that refer to the given string.
+    ///
+    /// The string must outlive the instructions that reference it, which holds
+    /// for the strings in the virtual DOMs being diffed.
+    #[cfg(not(target_pointer_width = "32"))]
+    #[inline]
+    pub fn string_immediates(&self, string: &str) -> (u32, u32) {
+        let mut strings = self.strings.borrow_mut();
+        strings.push((string.as_ptr(), string.len()));
+        ((strings.len() - 1) as u32, string.len() as u32)
+    }
+
+    /// Read back the string referred to by the given `(pointer, length)`
+    /// immediates.
+    ///
+    /// # Unsafety
+    ///
+    /// The immediates must have come from `string_immediates` since the last
+    /// `reset`, and the string must still be alive.
+    pub unsafe fn read_string(&self, pointer: u32, length: u32) -> &str {
+        #[cfg(target_pointer_width = "32")]
+        let pointer = pointer as usize as *const u8;
+        #[cfg(not(target_pointer_width = "32"))]
+        let pointer = {
+            let (pointer, len) = self.strings.borrow()[pointer as usize];
+            debug_assert_eq!(len, length as usize);
+            pointer
+        };
+        let bytes = std::slice::from_raw_parts(pointer, length as usize);
+        std::str::from_utf8_unchecked(bytes)
     }
 
     /// Invoke the given function with each of the allocated instruction
     /// sequences that this emitter has built up.
-    #[cfg_attr(feature = "xxx-unstable-internal-use-only", allow(dead_code))]
     pub fn each_instruction_sequence<F>(&mut self, f: F)
     where
         F: FnMut(&[u8]),
@@ -44,9 +94,24 @@ impl InstructionEmitter {
         }
     }
 
+    /// Collect all of the instructions that this emitter has built up into a
+    /// single `u32` stream.
+    pub fn instructions(&mut self) -> Vec<u32> {
+        let mut instructions = vec![];
+        self.each_instruction_sequence(|seq| {
+            instructions.extend(
+                seq.chunks(4)
+                    .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
+            );
+        });
+        instructions
+    }
+
     /// Reset to an empty sequence of instructions.
     pub fn reset(&mut self) {
         self.bump.reset();
+        #[cfg(not(target_pointer_width = "32"))]
+        self.strings.borrow_mut().clear();
     }
 }
 
@@ -69,6 +134,8 @@ macro_rules! define_change_list_instructions {
     }
 }
 
+// Note: when adding or changing instructions, also update the JS interpreter's
+// `OP_TABLE` and `disassembler::decode`.
 define_change_list_instructions! {
     /// Stack: `[... TextNode] -> [... TextNode]`
     ///
@@ -289,4 +356,128 @@ define_change_list_instructions! {
     /// stack.push(template.cloneNode(true))
     /// ```
     push_template(id) = 25,
+
+    /// Stack: `[...] -> [... Node]`
+    ///
+    /// ```text
+    /// stack.push(document.createComment(readString(pointer, length)))
+    /// ```
+    create_comment(pointer, length) = 26,
+
+    /// Stack: `[... Node] -> [... Node Node]`
+    ///
+    /// ```text
+    /// placeholder = stack.top()
+    /// stack.push(portals.get(placeholder)[index])
+    /// ```
+    push_portal_child(index) = 27,
+
+    /// Stack: `[... Node Node] -> [... Node]`
+    ///
+    /// ```text
+    /// child = stack.pop()
+    /// placeholder = stack.top()
+    /// children = portals.get(placeholder) || []
+    /// target = getPortalTarget(target_id)
+    /// if (child.parentNode !== target) {
+    ///     // Keep the portal's children in order.
+    ///     next = index > 0 ? children[index - 1].nextSibling : null
+    ///     target.insertBefore(child, next)
+    /// }
+    /// children[index] = child
+    /// portals.set(placeholder, children)
+    /// ```
+    mount_portal(target_id, index) = 28,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// element = stack.top()
+    /// nodeRefs.set(id, element)
+    /// nodeRefsTrampoline(id, element)
+    /// ```
+    set_node_ref(id) = 29,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// if (nodeRefs.get(id) === stack.top()) {
+    ///     nodeRefs.delete(id)
+    ///     nodeRefsTrampoline(id, null)
+    /// }
+    /// ```
+    remove_node_ref(id) = 30,
+
+    /// Stack: `[... Element] -> [... Element]`
+    ///
+    /// ```text
+    /// stack.top().innerHTML = readString(pointer, length)
+    /// ```
+    set_inner_html(pointer, length) = 31,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// name = stringsCache[name]
+    /// value = match kind {
+    ///     0 => readString(a, b),
+    ///     1 => a != 0,
+    ///     _ => f64::from_bits(a | (b << 32)),
+    /// }
+    /// if (stack.top()[name] !== value) {
+    ///     stack.top()[name] = value
+    /// }
+    /// ```
+    set_property(name, kind, a, b) = 32,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// name = stringsCache[name]
+    /// stack.top()[name] = emptyValueOfTheSameType(stack.top()[name])
+    /// ```
+    remove_property(name) = 33,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// name = stringsCache[name]
+    /// value = stringsCache[value]
+    /// stack.top().style.setProperty(name, value)
+    /// ```
+    set_style_property(name, value) = 34,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// name = stringsCache[name]
+    /// stack.top().style.removeProperty(name)
+    /// ```
+    remove_style_property(name) = 35,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// class = stringsCache[class]
+    /// stack.top().classList.add(class)
+    /// ```
+    add_class(class) = 36,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// class = stringsCache[class]
+    /// stack.top().classList.remove(class)
+    /// ```
+    remove_class(class) = 37,
+
+    /// Stack: `[... Node] -> [... Node]`
+    ///
+    /// ```text
+    /// placeholder = stack.top()
+    /// for (child of portals.get(placeholder).splice(len)) {
+    ///     child.remove()
+    /// }
+    /// ```
+    truncate_portal(len) = 38,
 }
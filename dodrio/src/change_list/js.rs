@@ -1,15 +1,6 @@
 cfg_if::cfg_if! {
     if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
-        #[derive(Clone, Debug)]
-        pub struct ChangeListInterpreter {}
-        impl ChangeListInterpreter {
-            pub fn new(_container: &crate::Element) -> ChangeListInterpreter {
-                ChangeListInterpreter {}
-            }
-            pub fn unmount(&self) {}
-            pub fn add_change_list_range(&self, _start: usize, _len: usize) {}
-            pub fn init_events_trampoline(&self, _trampoline: &crate::EventsTrampoline) {}
-        }
+        pub use crate::headless::ChangeListInterpreter;
     } else {
         use wasm_bindgen::prelude::*;
 
@@ -30,11 +21,20 @@ cfg_if::cfg_if! {
             #[wasm_bindgen(structural, method, js_name = applyChanges)]
             pub fn apply_changes(this: &ChangeListInterpreter, memory: JsValue);
 
+            #[wasm_bindgen(structural, method, js_name = addPortalTarget)]
+            pub fn add_portal_target(this: &ChangeListInterpreter, id: u32, target: &web_sys::Element);
+
             #[wasm_bindgen(structural, method, js_name = initEventsTrampoline)]
             pub fn init_events_trampoline(
                 this: &ChangeListInterpreter,
                 trampoline: &crate::EventsTrampoline,
             );
+
+            #[wasm_bindgen(structural, method, js_name = initNodeRefsTrampoline)]
+            pub fn init_node_refs_trampoline(
+                this: &ChangeListInterpreter,
+                trampoline: &crate::NodeRefsTrampoline,
+            );
         }
     }
 }
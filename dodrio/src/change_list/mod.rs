@@ -1,3 +1,4 @@
+pub(crate) mod disassembler;
 pub(crate) mod emitter;
 pub(crate) mod strings;
 pub(crate) mod traversal;
@@ -5,11 +6,20 @@ pub(crate) mod traversal;
 // Note: has to be `pub` because of `wasm-bindgen` visibility restrictions.
 pub mod js;
 
+use self::disassembler::{Disassembly, Instruction};
 use self::emitter::InstructionEmitter;
 use self::strings::{StringKey, StringsCache};
 use self::traversal::{MoveTo, Traversal};
-use crate::{cached_set::CacheId, Listener};
-use fxhash::FxHashSet;
+use crate::{
+    cached_set::CacheId,
+    lifecycle::LifecycleHooks,
+    node_ref::NodeRefId,
+    portal::PortalTarget,
+    profile::{RenderProfile, Stopwatch},
+    Listener, PropertyValue,
+};
+use fxhash::{FxHashMap, FxHashSet};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub(crate) struct ChangeListPersistentState {
@@ -18,12 +28,16 @@ pub(crate) struct ChangeListPersistentState {
     traversal: Traversal,
     interpreter: js::ChangeListInterpreter,
     templates: FxHashSet<CacheId>,
+    portal_targets: FxHashSet<PortalTarget>,
+    record_disassemblies: bool,
+    last_disassembly: Option<Disassembly>,
 }
 
 pub(crate) struct ChangeListBuilder<'a> {
     state: &'a mut ChangeListPersistentState,
     next_temporary: u32,
     forcing_new_listeners: bool,
+    lifecycle: LifecycleHooks,
 }
 
 impl Drop for ChangeListPersistentState {
@@ -38,6 +52,9 @@ impl ChangeListPersistentState {
         let emitter = InstructionEmitter::new();
         let traversal = Traversal::new();
         let interpreter = js::ChangeListInterpreter::new(container);
+        crate::node_ref::with_trampoline(|trampoline| {
+            interpreter.init_node_refs_trampoline(trampoline);
+        });
         let templates = Default::default();
         ChangeListPersistentState {
             strings,
@@ -45,18 +62,55 @@ impl ChangeListPersistentState {
             traversal,
             interpreter,
             templates,
+            portal_targets: Default::default(),
+            record_disassemblies: false,
+            last_disassembly: None,
         }
     }
 
+    /// Start or stop disassembling each change list as it is applied.
+    pub(crate) fn set_record_disassemblies(&mut self, record: bool) {
+        self.record_disassemblies = record;
+        if !record {
+            self.last_disassembly = None;
+        }
+    }
+
+    /// Get the disassembly of the last change list that was applied while
+    /// recording disassemblies.
+    pub(crate) fn last_disassembly(&self) -> Option<&Disassembly> {
+        self.last_disassembly.as_ref()
+    }
+
     pub(crate) fn init_events_trampoline(&mut self, trampoline: &crate::EventsTrampoline) {
         self.interpreter.init_events_trampoline(trampoline);
     }
 
+    // Fill in the change list parts of a render's profile, if we are
+    // profiling it.
+    fn profile(
+        &mut self,
+        profile: Option<&mut RenderProfile>,
+        instructions: &[Instruction],
+        apply_time: Duration,
+    ) {
+        let (strings_added, strings_dropped) = self.strings.take_counts();
+        if let Some(profile) = profile {
+            profile.apply_time = apply_time;
+            profile.strings_added = strings_added;
+            profile.strings_dropped = strings_dropped;
+            for instruction in instructions {
+                *profile.instructions.entry(instruction.name()).or_insert(0) += 1;
+            }
+        }
+    }
+
     pub(crate) fn builder<'a>(&'a mut self) -> ChangeListBuilder<'a> {
         ChangeListBuilder {
             state: self,
             next_temporary: 0,
             forcing_new_listeners: false,
+            lifecycle: LifecycleHooks::default(),
         }
     }
 }
@@ -64,10 +118,22 @@ impl ChangeListPersistentState {
 cfg_if::cfg_if! {
     if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
         impl ChangeListBuilder<'_> {
-            pub(crate) fn finish(self) {
+            pub(crate) fn finish(self, profile: Option<&mut RenderProfile>) {
+                let strings = self.strings_snapshot();
                 self.state.strings.drop_unused_strings(&self.state.emitter);
 
-                // Nothing to actually apply the changes to.
+                // Apply the changes to the headless DOM.
+                let instructions = self.state.emitter.instructions();
+                let instructions = disassembler::decode(&instructions, &self.state.emitter);
+                let stopwatch = Stopwatch::start(profile.is_some());
+                self.state.interpreter.apply_changes(&instructions);
+                let apply_time = stopwatch.elapsed();
+
+                self.state.profile(profile, &instructions, apply_time);
+
+                if let Some(strings) = strings {
+                    self.state.last_disassembly = Some(Disassembly::new(instructions, strings));
+                }
 
                 self.state.emitter.reset();
                 self.state.traversal.reset();
@@ -75,15 +141,33 @@ cfg_if::cfg_if! {
         }
     } else {
         impl ChangeListBuilder<'_> {
-            pub(crate) fn finish(self) {
+            pub(crate) fn finish(self, profile: Option<&mut RenderProfile>) {
+                let strings = self.strings_snapshot();
                 self.state.strings.drop_unused_strings(&self.state.emitter);
 
+                // Only decode the instructions if something is going to look
+                // at them.
+                let instructions = if strings.is_some() || profile.is_some() {
+                    let instructions = self.state.emitter.instructions();
+                    disassembler::decode(&instructions, &self.state.emitter)
+                } else {
+                    vec![]
+                };
+
                 // Apply the changes.
+                let stopwatch = Stopwatch::start(profile.is_some());
                 let interpreter = &self.state.interpreter;
                 self.state.emitter.each_instruction_sequence(|seq| {
                     interpreter.add_change_list_range(seq.as_ptr() as usize, seq.len());
                 });
                 interpreter.apply_changes(wasm_bindgen::memory());
+                let apply_time = stopwatch.elapsed();
+
+                self.state.profile(profile, &instructions, apply_time);
+
+                if let Some(strings) = strings {
+                    self.state.last_disassembly = Some(Disassembly::new(instructions, strings));
+                }
 
                 self.state.emitter.reset();
                 self.state.traversal.reset();
@@ -92,6 +176,19 @@ cfg_if::cfg_if! {
     }
 }
 
+impl ChangeListBuilder<'_> {
+    // If we are recording disassemblies, snapshot the strings cache before it
+    // drops unused strings, so that the disassembly can resolve the keys of
+    // the strings it drops.
+    fn strings_snapshot(&self) -> Option<FxHashMap<u32, String>> {
+        if self.state.record_disassemblies {
+            Some(self.state.strings.snapshot())
+        } else {
+            None
+        }
+    }
+}
+
 /// Traversal methods.
 impl ChangeListBuilder<'_> {
     pub fn go_down(&mut self) {
@@ -216,9 +313,15 @@ impl ChangeListBuilder<'_> {
     pub fn set_text(&self, text: &str) {
         debug_assert!(self.traversal_is_committed());
         debug!("emit: set_text({:?})", text);
-        self.state
-            .emitter
-            .set_text(text.as_ptr() as u32, text.len() as u32);
+        let (pointer, length) = self.state.emitter.string_immediates(text);
+        self.state.emitter.set_text(pointer, length);
+    }
+
+    pub fn set_inner_html(&self, html: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: set_inner_html({:?})", html);
+        let (pointer, length) = self.state.emitter.string_immediates(html);
+        self.state.emitter.set_inner_html(pointer, length);
     }
 
     pub fn remove_self_and_next_siblings(&self) {
@@ -256,6 +359,62 @@ impl ChangeListBuilder<'_> {
         self.state.emitter.remove_attribute(name_id.into());
     }
 
+    pub fn set_property(&mut self, name: &str, value: PropertyValue) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: set_property({:?}, {:?})", name, value);
+        let name_id = self.ensure_string(name);
+        let (kind, a, b) = match value {
+            PropertyValue::String(s) => {
+                let (pointer, length) = self.state.emitter.string_immediates(s);
+                (0, pointer, length)
+            }
+            PropertyValue::Bool(b) => (1, b as u32, 0),
+            PropertyValue::Number(n) => {
+                let bits = n.to_bits();
+                (2, bits as u32, (bits >> 32) as u32)
+            }
+        };
+        self.state.emitter.set_property(name_id.into(), kind, a, b);
+    }
+
+    pub fn remove_property(&mut self, name: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: remove_property({:?})", name);
+        let name_id = self.ensure_string(name);
+        self.state.emitter.remove_property(name_id.into());
+    }
+
+    pub fn set_style_property(&mut self, name: &str, value: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: set_style_property({:?}, {:?})", name, value);
+        let name_id = self.ensure_string(name);
+        let value_id = self.ensure_string(value);
+        self.state
+            .emitter
+            .set_style_property(name_id.into(), value_id.into());
+    }
+
+    pub fn remove_style_property(&mut self, name: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: remove_style_property({:?})", name);
+        let name_id = self.ensure_string(name);
+        self.state.emitter.remove_style_property(name_id.into());
+    }
+
+    pub fn add_class(&mut self, class: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: add_class({:?})", class);
+        let class_id = self.ensure_string(class);
+        self.state.emitter.add_class(class_id.into());
+    }
+
+    pub fn remove_class(&mut self, class: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: remove_class({:?})", class);
+        let class_id = self.ensure_string(class);
+        self.state.emitter.remove_class(class_id.into());
+    }
+
     pub fn append_child(&self) {
         debug_assert!(self.traversal_is_committed());
         debug!("emit: append_child()");
@@ -265,9 +424,15 @@ impl ChangeListBuilder<'_> {
     pub fn create_text_node(&self, text: &str) {
         debug_assert!(self.traversal_is_committed());
         debug!("emit: create_text_node({:?})", text);
-        self.state
-            .emitter
-            .create_text_node(text.as_ptr() as u32, text.len() as u32);
+        let (pointer, length) = self.state.emitter.string_immediates(text);
+        self.state.emitter.create_text_node(pointer, length);
+    }
+
+    pub fn create_comment(&self, text: &str) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: create_comment({:?})", text);
+        let (pointer, length) = self.state.emitter.string_immediates(text);
+        self.state.emitter.create_comment(pointer, length);
     }
 
     pub fn create_element(&mut self, tag_name: &str) {
@@ -350,4 +515,53 @@ impl ChangeListBuilder<'_> {
         debug!("emit: push_template({:?})", id);
         self.state.emitter.push_template(id.into());
     }
+
+    pub fn forcing_new_listeners(&self) -> bool {
+        self.forcing_new_listeners
+    }
+
+    /// The lifecycle hooks that diffing has queued up so far.
+    #[inline]
+    pub fn lifecycle(&mut self) -> &mut LifecycleHooks {
+        &mut self.lifecycle
+    }
+
+    pub fn set_node_ref(&self, id: NodeRefId) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: set_node_ref({:?})", id);
+        self.state.emitter.set_node_ref(id.into());
+    }
+
+    pub fn remove_node_ref(&self, id: NodeRefId) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: remove_node_ref({:?})", id);
+        self.state.emitter.remove_node_ref(id.into());
+    }
+
+    pub fn push_portal_child(&self, index: usize) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: push_portal_child({})", index);
+        self.state.emitter.push_portal_child(index as u32);
+    }
+
+    pub fn mount_portal(&mut self, target: PortalTarget, index: usize) {
+        debug_assert!(self.traversal_is_committed());
+
+        // Let the interpreter know about this target element, if it doesn't
+        // already. It will have been told before it applies this change list.
+        if self.state.portal_targets.insert(target) {
+            self.state
+                .interpreter
+                .add_portal_target(target.into(), &target.element());
+        }
+
+        debug!("emit: mount_portal({:?}, {})", target, index);
+        self.state.emitter.mount_portal(target.into(), index as u32);
+    }
+
+    pub fn truncate_portal(&self, len: usize) {
+        debug_assert!(self.traversal_is_committed());
+        debug!("emit: truncate_portal({})", len);
+        self.state.emitter.truncate_portal(len as u32);
+    }
 }
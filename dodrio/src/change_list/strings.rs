@@ -21,6 +21,10 @@ struct StringsCacheEntry {
 pub(crate) struct StringsCache {
     entries: FxHashMap<String, StringsCacheEntry>,
     next_string_key: u32,
+    // How many strings have been added and dropped since the last call to
+    // `take_counts`.
+    added: usize,
+    dropped: usize,
 }
 
 impl StringsCache {
@@ -38,13 +42,29 @@ impl StringsCache {
             let key = StringKey(self.next_string_key);
             self.next_string_key += 1;
             let entry = StringsCacheEntry { key, used: true };
-            self.entries.insert(string.to_string(), entry);
-            emitter.add_cached_string(string.as_ptr() as u32, string.len() as u32, key.into());
+            // The emitted instruction refers to the string's bytes, which are
+            // not read until the change list is applied. Point it at our own
+            // copy, whose heap allocation lives at least as long as this
+            // frame, rather than at `string`, which may be a temporary.
+            let string = string.to_string();
+            let (pointer, length) = emitter.string_immediates(&string);
+            emitter.add_cached_string(pointer, length, key.into());
+            self.entries.insert(string, entry);
+            self.added += 1;
             key
         }
     }
 
+    /// Get a copy of every cached string, keyed by its key.
+    pub fn snapshot(&self) -> FxHashMap<u32, String> {
+        self.entries
+            .iter()
+            .map(|(string, entry)| (entry.key.into(), string.clone()))
+            .collect()
+    }
+
     pub fn drop_unused_strings(&mut self, emitter: &InstructionEmitter) {
+        let dropped = &mut self.dropped;
         self.entries.retain(|string, entry| {
             if entry.used {
                 // Since this entry was used during while rendering this frame,
@@ -59,8 +79,18 @@ impl StringsCache {
                 let key = entry.key.into();
                 debug!("emit: drop_cached_string({}) = {:?}", key, string);
                 emitter.drop_cached_string(key);
+                *dropped += 1;
                 false
             }
         });
     }
+
+    /// Get the number of strings that were added and dropped since the last
+    /// call, and reset the counts.
+    pub fn take_counts(&mut self) -> (usize, usize) {
+        let counts = (self.added, self.dropped);
+        self.added = 0;
+        self.dropped = 0;
+        counts
+    }
 }
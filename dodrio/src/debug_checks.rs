@@ -0,0 +1,263 @@
+//! Validation of rendered virtual DOM trees, enabled by the `debug-checks`
+//! cargo feature.
+//!
+//! The diffing algorithm assumes some invariants that the types don't enforce,
+//! and quietly produces the wrong physical DOM when they are broken. With
+//! `debug-checks` enabled, each new tree is validated before it is diffed, and
+//! the first broken invariant panics with the path to the offending node, such
+//! as `div > ul > li[3]`.
+//!
+//! Listeners can only be attached to elements, never to text nodes, so that
+//! invariant is already enforced by the types and isn't checked here. Likewise,
+//! `ElementBuilder::class` splits class names on whitespace, so each class is
+//! already a single, non-empty token.
+
+use crate::{
+    cached_set::CachedSet,
+    node::{Node, NodeKind},
+};
+use fxhash::FxHashSet;
+use std::fmt;
+
+/// Validate the given tree, including the cached subtrees that it references,
+/// and panic if it is invalid.
+pub(crate) fn validate<'a>(cached_set: &'a CachedSet, root: &'a Node<'a>) {
+    let mut path = Path(vec![]);
+    validate_children(cached_set, &mut path, std::slice::from_ref(root));
+}
+
+// The tag names and sibling indices of the nodes from the root to the node
+// that is being validated.
+struct Path<'a>(Vec<(&'a str, Option<usize>)>);
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, index)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " > ")?;
+            }
+            write!(f, "{}", name)?;
+            if let Some(index) = index {
+                write!(f, "[{}]", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(path: &Path, message: fmt::Arguments) -> ! {
+    panic!("invalid virtual DOM tree at `{}`: {}", path, message)
+}
+
+fn validate_children<'a>(cached_set: &'a CachedSet, path: &mut Path<'a>, children: &'a [Node<'a>]) {
+    // Fragments are spliced into their parent's children before diffing, so
+    // the invariants apply to the spliced list, and indices are into it.
+    let children = cached_set.flatten(children, &mut |_| {});
+
+    if let Some(first) = children.first() {
+        let keyed = first.key().is_some();
+        let mut keys = FxHashSet::default();
+        for (i, child) in children.iter().enumerate() {
+            let key = child.key();
+            if key.is_some() != keyed {
+                // Empty nodes usually come from `None` optional children, and
+                // can't be keyed at all, so point at them specifically.
+                if let Some((i, empty)) = [(0, first), (i, child)]
+                    .iter()
+                    .find(|(_, node)| is_empty(cached_set, node))
+                {
+                    path.0.push((name(cached_set, empty), Some(*i)));
+                    invalid(
+                        path,
+                        format_args!(
+                            "empty nodes cannot be keyed, so they cannot be siblings of keyed \
+                             nodes; filter `None` children out of keyed lists instead of \
+                             rendering them as empty nodes"
+                        ),
+                    );
+                }
+                let keyed = |k: bool| if k { "keyed" } else { "not keyed" };
+                path.0.push((name(cached_set, child), Some(i)));
+                invalid(
+                    path,
+                    format_args!(
+                        "if any sibling is keyed, all siblings must be keyed, but this child \
+                         is {} and the first child is {}",
+                        keyed(key.is_some()),
+                        keyed(first.key().is_some()),
+                    ),
+                );
+            }
+            if keyed && !keys.insert(key) {
+                path.0.push((name(cached_set, child), Some(i)));
+                invalid(
+                    path,
+                    format_args!(
+                        "keyed siblings must each have a unique key, but key {} is repeated",
+                        key.0
+                    ),
+                );
+            }
+        }
+    }
+
+    // Only show indices for nodes that have siblings, to keep paths short.
+    let index = |i| if children.len() > 1 { Some(i) } else { None };
+    for (i, child) in children.iter().enumerate() {
+        validate_node(cached_set, path, child, index(i));
+    }
+}
+
+fn validate_node<'a>(
+    cached_set: &'a CachedSet,
+    path: &mut Path<'a>,
+    node: &Node<'a>,
+    index: Option<usize>,
+) {
+    path.0.push((name(cached_set, node), index));
+    match node.kind {
+        NodeKind::Text(_) | NodeKind::RawHtml(_) | NodeKind::Empty => {}
+        NodeKind::Element(element) => {
+            if element.tag_name.is_empty() {
+                invalid(path, format_args!("elements must have a tag name"));
+            }
+            if !element.classes.is_empty() && element.attributes.iter().any(|a| a.name == "class") {
+                invalid(
+                    path,
+                    format_args!(
+                        "elements with classes cannot also have a `class` attribute, since \
+                         they would overwrite each other"
+                    ),
+                );
+            }
+            validate_children(cached_set, path, element.children);
+        }
+        NodeKind::Cached(ref cached) => {
+            // The cached subtree stands in for this node, so validate it at
+            // the same path.
+            path.0.pop();
+            let (node, _) = cached_set.get(cached.id);
+            validate_node(cached_set, path, node, index);
+            return;
+        }
+        NodeKind::Portal(portal) => {
+            // A fragment child is spliced into the portal's children, just
+            // like into an element's.
+            validate_children(cached_set, path, std::slice::from_ref(&portal.child));
+        }
+        NodeKind::Fragment(_) => unreachable!("fragments are spliced into their parent"),
+    }
+    path.0.pop();
+}
+
+// Whether the node renders as an empty node, possibly through a cached subtree.
+fn is_empty(cached_set: &CachedSet, node: &Node) -> bool {
+    match node.kind {
+        NodeKind::Empty => true,
+        NodeKind::Cached(ref cached) => is_empty(cached_set, cached_set.get(cached.id).0),
+        _ => false,
+    }
+}
+
+// The name of a node in a path.
+fn name<'a>(cached_set: &'a CachedSet, node: &Node<'a>) -> &'a str {
+    match node.kind {
+        NodeKind::Text(_) => "#text",
+        NodeKind::Element(element) => element.tag_name,
+        NodeKind::Cached(ref cached) => name(cached_set, cached_set.get(cached.id).0),
+        NodeKind::Portal(_) => "portal",
+        NodeKind::RawHtml(ref raw) => raw.tag_name,
+        NodeKind::Empty => "#comment",
+        NodeKind::Fragment(_) => "fragment",
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::testing::*;
+    use crate::{builder::*, Node, Render, RenderContext};
+
+    #[test]
+    #[should_panic(
+        expected = "invalid virtual DOM tree at `div > ul > li[3]`: keyed siblings must each \
+                    have a unique key, but key 1 is repeated"
+    )]
+    fn duplicate_keys() {
+        let (_container, _vdom) = mount(Div(vec![list(&[0, 1, 2, 1], true)]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "invalid virtual DOM tree at `div > ul > #text[1]`: if any sibling is keyed, \
+                    all siblings must be keyed, but this child is not keyed and the first \
+                    child is keyed"
+    )]
+    fn mixed_keyed_and_unkeyed_siblings() {
+        struct Mixed;
+
+        impl Render for Mixed {
+            fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+                ul(&cx)
+                    .children([li(&cx).key(0).finish(), text("oops")])
+                    .finish()
+            }
+        }
+
+        let (_container, _vdom) = mount(Div(vec![Mixed]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "invalid virtual DOM tree at `div > ul > #comment[1]`: empty nodes cannot be \
+                    keyed, so they cannot be siblings of keyed nodes; filter `None` children \
+                    out of keyed lists instead of rendering them as empty nodes"
+    )]
+    fn empty_nodes_among_keyed_siblings() {
+        struct Optional;
+
+        impl Render for Optional {
+            fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+                let missing: Option<Node> = None;
+                ul(&cx)
+                    .children([li(&cx).key(0).finish(), missing.into()])
+                    .finish()
+            }
+        }
+
+        let (_container, _vdom) = mount(Div(vec![Optional]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "invalid virtual DOM tree at `div > p > [1]`: elements must have a tag name"
+    )]
+    fn empty_tag_names() {
+        struct Untagged;
+
+        impl Render for Untagged {
+            fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+                let untagged = ElementBuilder::new(cx.bump, "").finish();
+                p(&cx).children([text("a"), untagged]).finish()
+            }
+        }
+
+        let (_container, _vdom) = mount(Div(vec![Untagged]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "invalid virtual DOM tree at `div > li`: elements with classes cannot also \
+                    have a `class` attribute, since they would overwrite each other"
+    )]
+    fn classes_and_class_attributes() {
+        struct Todo;
+
+        impl Render for Todo {
+            fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+                li(&cx).attr("class", "todo").class("completed").finish()
+            }
+        }
+
+        let (_container, _vdom) = mount(Div(vec![Todo]));
+    }
+}
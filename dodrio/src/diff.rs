@@ -2,10 +2,17 @@ use crate::{
     cached_set::{CacheId, CachedSet},
     change_list::ChangeListBuilder,
     events::EventsRegistry,
-    node::{Attribute, ElementNode, Listener, Node, NodeKind, TextNode},
+    lifecycle::LifecycleMode,
+    node::{
+        Attribute, ElementNode, Listener, Node, NodeKind, PortalNode, Property, RawHtmlNode, Style,
+        TextNode,
+    },
+    node_ref::NodeRefId,
+    portal::PortalTarget,
 };
 use fxhash::{FxHashMap, FxHashSet};
 use std::cmp::Ordering;
+use std::slice;
 use std::u32;
 use wasm_bindgen::UnwrapThrowExt;
 
@@ -27,6 +34,8 @@ pub(crate) fn diff(
     cached_roots: &mut FxHashSet<CacheId>,
 ) {
     match (&new.kind, &old.kind) {
+        (&NodeKind::Fragment(_), _) | (_, &NodeKind::Fragment(_)) => fragment_outside_children(),
+
         (
             &NodeKind::Text(TextNode { text: new_text }),
             &NodeKind::Text(TextNode { text: old_text }),
@@ -40,7 +49,7 @@ pub(crate) fn diff(
         (&NodeKind::Text(_), &NodeKind::Element(_)) => {
             change_list.commit_traversal();
             create(cached_set, change_list, registry, new, cached_roots);
-            registry.remove_subtree(&old);
+            remove_subtree(cached_set, change_list, registry, old);
             change_list.replace_with();
         }
 
@@ -58,27 +67,50 @@ pub(crate) fn diff(
                 tag_name: new_tag_name,
                 listeners: new_listeners,
                 attributes: new_attributes,
+                properties: new_properties,
+                styles: new_styles,
+                classes: new_classes,
                 children: new_children,
                 namespace: new_namespace,
+                node_ref: new_node_ref,
+                on_mount: new_on_mount,
+                on_unmount: _,
             }),
             &NodeKind::Element(ElementNode {
                 key: _,
                 tag_name: old_tag_name,
                 listeners: old_listeners,
                 attributes: old_attributes,
+                properties: old_properties,
+                styles: old_styles,
+                classes: old_classes,
                 children: old_children,
                 namespace: old_namespace,
+                node_ref: old_node_ref,
+                on_mount: _,
+                on_unmount: _,
             }),
         ) => {
             if new_tag_name != old_tag_name || new_namespace != old_namespace {
                 change_list.commit_traversal();
                 create(cached_set, change_list, registry, new, cached_roots);
-                registry.remove_subtree(&old);
+                remove_subtree(cached_set, change_list, registry, old);
                 change_list.replace_with();
                 return;
             }
+            if change_list.lifecycle().mounting_template() {
+                unsafe {
+                    // Safety relies on the `Vdom` invoking the queued hooks
+                    // before the end of this render.
+                    change_list.lifecycle().mounted(*new_on_mount);
+                }
+            }
             diff_listeners(change_list, registry, old_listeners, new_listeners);
             diff_attributes(change_list, old_attributes, new_attributes);
+            diff_properties(change_list, old_properties, new_properties);
+            diff_styles(change_list, old_styles, new_styles);
+            diff_classes(change_list, old_classes, new_classes);
+            diff_node_ref(change_list, *old_node_ref, *new_node_ref);
             diff_children(
                 cached_set,
                 change_list,
@@ -94,7 +126,17 @@ pub(crate) fn diff(
             cached_roots.insert(new.id);
 
             if new.id == old.id {
-                // This is the same cached node, so nothing has changed!
+                // This is the same cached node, so nothing has changed! Unless
+                // the old node is part of a template, in which case the new
+                // node is being mounted.
+                if change_list.lifecycle().mounting_template() {
+                    let (new, _) = cached_set.get(new.id);
+                    unsafe {
+                        // Safety relies on the `Vdom` invoking the queued
+                        // hooks before the end of this render.
+                        change_list.lifecycle().mounted_subtree(cached_set, new);
+                    }
+                }
                 return;
             }
 
@@ -144,9 +186,169 @@ pub(crate) fn diff(
         (_, &NodeKind::Cached(_)) => {
             change_list.commit_traversal();
             create(cached_set, change_list, registry, new, cached_roots);
-            registry.remove_subtree(&old);
+            remove_subtree(cached_set, change_list, registry, old);
+            change_list.replace_with();
+        }
+
+        (&NodeKind::Portal(new), &NodeKind::Portal(old)) if new.target == old.target => {
+            diff_portal(cached_set, change_list, registry, old, new, cached_roots);
+        }
+
+        // Portals into different targets, or a portal and a non-portal
+        // node. Replace the old node with the new one. When an old portal's
+        // placeholder is removed, the interpreter removes its child from its
+        // target as well.
+        (&NodeKind::Portal(_), _) | (_, &NodeKind::Portal(_)) => {
+            change_list.commit_traversal();
+            create(cached_set, change_list, registry, new, cached_roots);
+            remove_subtree(cached_set, change_list, registry, old);
+            change_list.replace_with();
+        }
+
+        (
+            &NodeKind::RawHtml(RawHtmlNode {
+                tag_name: new_tag_name,
+                namespace: new_namespace,
+                html: new_html,
+            }),
+            &NodeKind::RawHtml(RawHtmlNode {
+                tag_name: old_tag_name,
+                namespace: old_namespace,
+                html: old_html,
+            }),
+        ) if new_tag_name == old_tag_name && new_namespace == old_namespace => {
+            if new_html != old_html {
+                change_list.commit_traversal();
+                change_list.set_inner_html(new_html);
+            }
+        }
+
+        // Raw HTML with a different wrapper, or raw HTML and an element or text
+        // node. Even when the old node is an element with the same tag name as
+        // the raw HTML's wrapper, replace it, since its children are not in
+        // sync with the new markup.
+        (&NodeKind::RawHtml(_), _) | (_, &NodeKind::RawHtml(_)) => {
+            change_list.commit_traversal();
+            create(cached_set, change_list, registry, new, cached_roots);
+            remove_subtree(cached_set, change_list, registry, old);
             change_list.replace_with();
         }
+
+        (&NodeKind::Empty, &NodeKind::Empty) => {}
+
+        (&NodeKind::Empty, _) | (_, &NodeKind::Empty) => {
+            change_list.commit_traversal();
+            create(cached_set, change_list, registry, new, cached_roots);
+            remove_subtree(cached_set, change_list, registry, old);
+            change_list.replace_with();
+        }
+    }
+}
+
+// Diff two portals into the same target.
+//
+// A portal's children are its child with any fragments spliced away. Each of
+// them is mounted into the target, in order, as one physical DOM node. The old
+// and new children are diffed pairwise, any extra new children are created and
+// mounted after them, and any extra old children are removed.
+//
+// Upon entry to this function, the old portal's placeholder must be on top of
+// the change list stack:
+//
+//     [... placeholder]
+//
+// The change list stack is in the same state when this function exits.
+fn diff_portal(
+    cached_set: &CachedSet,
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    old: &PortalNode,
+    new: &PortalNode,
+    cached_roots: &mut FxHashSet<CacheId>,
+) {
+    change_list.commit_traversal();
+
+    let old_children = cached_set.flatten(slice::from_ref(&old.child), &mut |_| {});
+    let new_children = cached_set.flatten(slice::from_ref(&new.child), &mut |id| {
+        cached_roots.insert(id);
+    });
+
+    // Placeholders cloned from a template do not have portal children, just
+    // like cloned elements do not have their listeners, so create the children
+    // from scratch.
+    if change_list.forcing_new_listeners() {
+        create_portal_children(
+            cached_set,
+            change_list,
+            registry,
+            new.target,
+            &new_children,
+            0,
+            cached_roots,
+        );
+        return;
+    }
+
+    for (i, (old_child, new_child)) in old_children.iter().zip(new_children.iter()).enumerate() {
+        // [... placeholder]
+        change_list.push_portal_child(i);
+        // [... placeholder child]
+        diff(
+            cached_set,
+            change_list,
+            registry,
+            old_child,
+            new_child,
+            cached_roots,
+        );
+        change_list.commit_traversal();
+        // [... placeholder child]
+        change_list.mount_portal(new.target, i);
+        // [... placeholder]
+    }
+
+    if new_children.len() > old_children.len() {
+        create_portal_children(
+            cached_set,
+            change_list,
+            registry,
+            new.target,
+            &new_children,
+            old_children.len(),
+            cached_roots,
+        );
+    } else if old_children.len() > new_children.len() {
+        for old_child in &old_children[new_children.len()..] {
+            remove_subtree(cached_set, change_list, registry, old_child);
+        }
+        change_list.truncate_portal(new_children.len());
+    }
+}
+
+// Create a portal's children from the `start`th onwards, and mount each of
+// them into the portal's target.
+//
+// Upon entry to this function, the portal's placeholder must be on top of the
+// change list stack:
+//
+//     [... placeholder]
+//
+// The change list stack is in the same state when this function exits.
+pub(crate) fn create_portal_children(
+    cached_set: &CachedSet,
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    target: PortalTarget,
+    children: &[Node],
+    start: usize,
+    cached_roots: &mut FxHashSet<CacheId>,
+) {
+    for (i, child) in children.iter().enumerate().skip(start) {
+        // [... placeholder]
+        create(cached_set, change_list, registry, child, cached_roots);
+        // [... placeholder child]
+        change_list.mount_portal(target, i);
+        // [... placeholder]
     }
 }
 
@@ -239,6 +441,129 @@ fn diff_attributes(change_list: &mut ChangeListBuilder, old: &[Attribute], new:
     }
 }
 
+// Diff properties between `old` and `new`.
+//
+// Properties can change via user input that we can't see when diffing against
+// the old virtual DOM, so every new property is set again, and the interpreter
+// compares it against the physical DOM node's live property instead.
+//
+// The properties' node must be on top of the change list stack:
+//
+//     [... node]
+//
+// The change list stack is left unchanged.
+fn diff_properties(change_list: &mut ChangeListBuilder, old: &[Property], new: &[Property]) {
+    for new_prop in new {
+        change_list.commit_traversal();
+        change_list.set_property(new_prop.name, new_prop.value);
+    }
+
+    for old_prop in old {
+        if new.iter().all(|new_prop| new_prop.name != old_prop.name) {
+            change_list.commit_traversal();
+            change_list.remove_property(old_prop.name);
+        }
+    }
+}
+
+// Diff inline style declarations between `old` and `new`, property by
+// property.
+//
+// The styles' node must be on top of the change list stack:
+//
+//     [... node]
+//
+// The change list stack is left unchanged.
+fn diff_styles(change_list: &mut ChangeListBuilder, old: &[Style], new: &[Style]) {
+    // Do O(n^2) passes to add/update and remove declarations, since there are
+    // almost always very few of them.
+    for new_style in new {
+        match old
+            .iter()
+            .find(|old_style| old_style.property == new_style.property)
+        {
+            Some(old_style) if old_style.value == new_style.value => {}
+            _ => {
+                change_list.commit_traversal();
+                change_list.set_style_property(new_style.property, new_style.value);
+            }
+        }
+    }
+
+    for old_style in old {
+        if new
+            .iter()
+            .all(|new_style| new_style.property != old_style.property)
+        {
+            change_list.commit_traversal();
+            change_list.remove_style_property(old_style.property);
+        }
+    }
+}
+
+// Diff class tokens between `old` and `new`, and add or remove only the classes
+// that changed.
+//
+// The classes' node must be on top of the change list stack:
+//
+//     [... node]
+//
+// The change list stack is left unchanged.
+fn diff_classes(change_list: &mut ChangeListBuilder, old: &[&str], new: &[&str]) {
+    // Do O(n^2) passes to add and remove classes, since there are almost
+    // always very few of them.
+    for new_class in new {
+        if !old.contains(new_class) {
+            change_list.commit_traversal();
+            change_list.add_class(new_class);
+        }
+    }
+
+    for old_class in old {
+        if !new.contains(old_class) {
+            change_list.commit_traversal();
+            change_list.remove_class(old_class);
+        }
+    }
+}
+
+// Diff a node's node refs.
+//
+// The node must be on top of the change list stack:
+//
+//     [... node]
+//
+// The change list stack is left unchanged.
+fn diff_node_ref(
+    change_list: &mut ChangeListBuilder,
+    old: Option<NodeRefId>,
+    new: Option<NodeRefId>,
+) {
+    // Nodes cloned from a template are not attached to any node ref yet, just
+    // like they don't have any listeners.
+    if old == new && !change_list.forcing_new_listeners() {
+        return;
+    }
+
+    change_list.commit_traversal();
+    if let Some(old) = old {
+        if Some(old) != new {
+            change_list.remove_node_ref(old);
+        }
+    }
+    if let Some(new) = new {
+        change_list.set_node_ref(new);
+    }
+}
+
+// Fragments are spliced into their parent's children, or into a portal's
+// children, before diffing, creating, or hydrating them, so that every node
+// that is diffed, created, or hydrated individually is exactly one physical DOM
+// node.
+pub(crate) fn fragment_outside_children() -> ! {
+    panic!("fragments can only be rendered as the children of elements or as the root")
+}
+
 // Diff the given set of old and new children.
 //
 // The parent must be on top of the change list stack when this function is
@@ -247,7 +572,7 @@ fn diff_attributes(change_list: &mut ChangeListBuilder, old: &[Attribute], new:
 //     [... parent]
 //
 // the change list stack is in the same state when this function returns.
-fn diff_children(
+pub(crate) fn diff_children(
     cached_set: &CachedSet,
     change_list: &mut ChangeListBuilder,
     registry: &mut EventsRegistry,
@@ -255,10 +580,16 @@ fn diff_children(
     new: &[Node],
     cached_roots: &mut FxHashSet<CacheId>,
 ) {
+    let old = cached_set.flatten(old, &mut |_| {});
+    let new = cached_set.flatten(new, &mut |id| {
+        cached_roots.insert(id);
+    });
+    let (old, new) = (&old[..], &new[..]);
+
     if new.is_empty() {
         if !old.is_empty() {
             change_list.commit_traversal();
-            remove_all_children(change_list, registry, old);
+            remove_all_children(cached_set, change_list, registry, old);
         }
         return;
     }
@@ -284,7 +615,7 @@ fn diff_children(
                 change_list.commit_traversal();
                 change_list.set_text(text);
                 for o in old {
-                    registry.remove_subtree(o);
+                    remove_subtree(cached_set, change_list, registry, o);
                 }
                 return;
             }
@@ -490,7 +821,12 @@ fn diff_keyed_prefix(
     if shared_prefix_count == new.len() {
         change_list.go_to_sibling(shared_prefix_count);
         change_list.commit_traversal();
-        remove_self_and_next_siblings(change_list, registry, &old[shared_prefix_count..]);
+        remove_self_and_next_siblings(
+            cached_set,
+            change_list,
+            registry,
+            &old[shared_prefix_count..],
+        );
         return KeyedPrefixResult::Finished;
     }
 
@@ -559,11 +895,16 @@ fn diff_keyed_middle(
     if shared_suffix_count == 0 && shared_keys.is_empty() {
         if shared_prefix_count == 0 {
             change_list.commit_traversal();
-            remove_all_children(change_list, registry, old);
+            remove_all_children(cached_set, change_list, registry, old);
         } else {
             change_list.go_down_to_child(shared_prefix_count);
             change_list.commit_traversal();
-            remove_self_and_next_siblings(change_list, registry, &old[shared_prefix_count..]);
+            remove_self_and_next_siblings(
+                cached_set,
+                change_list,
+                registry,
+                &old[shared_prefix_count..],
+            );
         }
         create_and_append_children(cached_set, change_list, registry, new, cached_roots);
         return;
@@ -606,7 +947,7 @@ fn diff_keyed_middle(
     let mut removed_count = 0;
     for (i, old_child) in old.iter().enumerate().rev() {
         if !shared_keys.contains(&old_child.key()) {
-            registry.remove_subtree(old_child);
+            remove_subtree(cached_set, change_list, registry, old_child);
             change_list.commit_traversal();
             change_list.remove_child(i + shared_prefix_count);
             removed_count += 1;
@@ -823,7 +1164,7 @@ fn diff_non_keyed_children(
             change_list.go_to_sibling(new.len());
             // [... parent first_child_to_remove]
             change_list.commit_traversal();
-            remove_self_and_next_siblings(change_list, registry, &old[new.len()..]);
+            remove_self_and_next_siblings(cached_set, change_list, registry, &old[new.len()..]);
             // [... parent]
         }
         Ordering::Less => {
@@ -854,7 +1195,7 @@ fn diff_non_keyed_children(
 //     [... parent]
 //
 // When this function returns, the change list stack is in the same state.
-fn create_and_append_children(
+pub(crate) fn create_and_append_children(
     cached_set: &CachedSet,
     change_list: &mut ChangeListBuilder,
     registry: &mut EventsRegistry,
@@ -862,7 +1203,10 @@ fn create_and_append_children(
     cached_roots: &mut FxHashSet<CacheId>,
 ) {
     debug_assert!(change_list.traversal_is_committed());
-    for child in new {
+    let new = cached_set.flatten(new, &mut |id| {
+        cached_roots.insert(id);
+    });
+    for child in new.iter() {
         create(cached_set, change_list, registry, child, cached_roots);
         change_list.append_child();
     }
@@ -876,13 +1220,14 @@ fn create_and_append_children(
 //
 // When this function returns, the change list stack is in the same state.
 fn remove_all_children(
+    cached_set: &CachedSet,
     change_list: &mut ChangeListBuilder,
     registry: &mut EventsRegistry,
     old: &[Node],
 ) {
     debug_assert!(change_list.traversal_is_committed());
     for child in old {
-        registry.remove_subtree(child);
+        remove_subtree(cached_set, change_list, registry, child);
     }
     // Fast way to remove all children: set the node's textContent to an empty
     // string.
@@ -899,17 +1244,34 @@ fn remove_all_children(
 //
 //     [... parent]
 fn remove_self_and_next_siblings(
+    cached_set: &CachedSet,
     change_list: &mut ChangeListBuilder,
     registry: &mut EventsRegistry,
     old: &[Node],
 ) {
     debug_assert!(change_list.traversal_is_committed());
     for child in old {
-        registry.remove_subtree(child);
+        remove_subtree(cached_set, change_list, registry, child);
     }
     change_list.remove_self_and_next_siblings();
 }
 
+// Forget a subtree that is about to be removed from the physical DOM: queue
+// its elements' unmount hooks and remove its listeners from the registry.
+fn remove_subtree(
+    cached_set: &CachedSet,
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    node: &Node,
+) {
+    unsafe {
+        // Safety relies on the `Vdom` invoking the queued hooks before the end
+        // of this render, while the old nodes are still alive.
+        change_list.lifecycle().unmounting_subtree(cached_set, node);
+    }
+    registry.remove_subtree(node);
+}
+
 // Emit instructions to create the given virtual node.
 //
 // The change list stack may have any shape upon entering this function:
@@ -919,7 +1281,7 @@ fn remove_self_and_next_siblings(
 // When this function returns, the new node is on top of the change list stack:
 //
 //     [... node]
-fn create(
+pub(crate) fn create(
     cached_set: &CachedSet,
     change_list: &mut ChangeListBuilder,
     registry: &mut EventsRegistry,
@@ -936,8 +1298,14 @@ fn create(
             tag_name,
             listeners,
             attributes,
+            properties,
+            styles,
+            classes,
             children,
             namespace,
+            node_ref,
+            on_mount,
+            on_unmount: _,
         }) => {
             if let Some(namespace) = namespace {
                 change_list.create_element_ns(tag_name, namespace);
@@ -956,6 +1324,32 @@ fn create(
                 change_list.set_attribute(&attr.name, &attr.value);
             }
 
+            for prop in properties {
+                change_list.set_property(prop.name, prop.value);
+            }
+
+            for style in styles {
+                change_list.set_style_property(style.property, style.value);
+            }
+
+            for class in classes {
+                change_list.add_class(class);
+            }
+
+            if let Some(node_ref) = node_ref {
+                change_list.set_node_ref(node_ref);
+            }
+
+            unsafe {
+                // Safety relies on the `Vdom` invoking the queued hooks before
+                // the end of this render.
+                change_list.lifecycle().mounted(on_mount);
+            }
+
+            let children = cached_set.flatten(children, &mut |id| {
+                cached_roots.insert(id);
+            });
+
             // Fast path: if there is a single text child, it is faster to
             // create-and-append the text node all at once via setting the
             // parent's `textContent` in a single change list instruction than
@@ -972,11 +1366,46 @@ fn create(
                 }
             }
 
-            for child in children {
+            for child in children.iter() {
                 create(cached_set, change_list, registry, child, cached_roots);
                 change_list.append_child();
             }
         }
+        NodeKind::Fragment(_) => fragment_outside_children(),
+        NodeKind::RawHtml(RawHtmlNode {
+            tag_name,
+            namespace,
+            html,
+        }) => {
+            if let Some(namespace) = namespace {
+                change_list.create_element_ns(tag_name, namespace);
+            } else {
+                change_list.create_element(tag_name);
+            }
+            change_list.set_inner_html(html);
+        }
+        NodeKind::Empty => {
+            // Not an empty comment, which `render_to_string` uses to separate
+            // text nodes and hydration removes.
+            change_list.create_comment("none");
+        }
+        NodeKind::Portal(&PortalNode { target, ref child }) => {
+            // [...]
+            change_list.create_comment("portal");
+            // [... placeholder]
+            let children = cached_set.flatten(slice::from_ref(child), &mut |id| {
+                cached_roots.insert(id);
+            });
+            create_portal_children(
+                cached_set,
+                change_list,
+                registry,
+                target,
+                &children,
+                0,
+                cached_roots,
+            );
+        }
         NodeKind::Cached(ref c) => {
             cached_roots.insert(c.id);
             let (node, template) = cached_set.get(c.id);
@@ -1032,7 +1461,11 @@ fn get_or_create_template<'a>(
         (template, true)
     } else {
         // [...]
+        let old_mode = change_list
+            .lifecycle()
+            .push_mode(LifecycleMode::CreatingTemplate);
         create(cached_set, change_list, registry, template, cached_roots);
+        change_list.lifecycle().pop_mode(old_mode);
         // [... template]
         change_list.save_template(template_id);
         // [... template]
@@ -1062,6 +1495,10 @@ fn create_and_replace(
             old_forcing = Some(change_list.push_force_new_listeners());
         }
 
+        // Every node that is diffed against the template is being mounted.
+        let old_mode = change_list
+            .lifecycle()
+            .push_mode(LifecycleMode::MountingTemplate);
         diff(
             cached_set,
             change_list,
@@ -1070,6 +1507,7 @@ fn create_and_replace(
             new,
             cached_roots,
         );
+        change_list.lifecycle().pop_mode(old_mode);
 
         if let Some(old) = old_forcing {
             change_list.pop_force_new_listeners(old);
@@ -1080,7 +1518,7 @@ fn create_and_replace(
         create(cached_set, change_list, registry, new, cached_roots);
         change_list.replace_with();
     }
-    registry.remove_subtree(old);
+    remove_subtree(cached_set, change_list, registry, old);
 }
 
 fn create_with_template(
@@ -1107,6 +1545,10 @@ fn create_with_template(
         old_forcing = Some(change_list.push_force_new_listeners());
     }
 
+    // Every node that is diffed against the template is being mounted.
+    let old_mode = change_list
+        .lifecycle()
+        .push_mode(LifecycleMode::MountingTemplate);
     diff(
         cached_set,
         change_list,
@@ -1115,6 +1557,7 @@ fn create_with_template(
         node,
         cached_roots,
     );
+    change_list.lifecycle().pop_mode(old_mode);
 
     if let Some(old) = old_forcing {
         change_list.pop_force_new_listeners(old);
@@ -1123,3 +1566,389 @@ fn create_with_template(
     // Make sure that we come back up to the level we were at originally.
     change_list.commit_traversal();
 }
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Cached, Node, Render, RenderContext};
+
+    #[test]
+    fn mount_and_diff() {
+        for &keyed in &[false, true] {
+            let (container, vdom) = mount(list(&[1, 2, 3], keyed));
+            assert_eq!(container.inner_html(), items_html(&[1, 2, 3]));
+
+            let ul = container.first_child().unwrap();
+            assert_eq!(ul.event_listeners(), vec!["click".to_string()]);
+
+            for items in &[
+                &[1, 2, 3, 4][..],
+                &[4, 3, 2, 1],
+                &[2, 5, 4],
+                &[],
+                &[6, 1, 2, 3, 7],
+                &[3, 1, 7, 6, 2],
+            ] {
+                vdom.immediately_render_and_diff(list(items, keyed));
+                assert_eq!(container.inner_html(), items_html(items));
+            }
+
+            // The root element was diffed in place rather than re-created.
+            assert_eq!(container.first_child().unwrap(), ul);
+        }
+    }
+
+    #[test]
+    fn keyed_children_keep_their_physical_nodes() {
+        let (container, vdom) = mount(list(&[1, 2, 3], true));
+        let ul = container.first_child().unwrap();
+        let before = ul.child_nodes();
+
+        vdom.immediately_render_and_diff(list(&[3, 1, 2], true));
+        let after = ul.child_nodes();
+        assert_eq!(
+            after,
+            vec![before[2].clone(), before[0].clone(), before[1].clone()]
+        );
+    }
+
+    // A root fragment whose list items are split across two nested fragments.
+    struct Split(Vec<u32>, usize);
+
+    impl Render for Split {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let items = |items: &[u32]| {
+                let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+                for &i in items {
+                    let label = bumpalo::format!(in cx.bump, "item {}", i).into_bump_str();
+                    let li = li(&cx).attr("class", "item").key(i);
+                    children.push(li.children([text(label)]).finish());
+                }
+                fragment(&cx, children)
+            };
+            let (first, second) = self.0.split_at(self.1);
+            fragment(
+                &cx,
+                [
+                    ul(&cx).children([items(first), items(second)]).finish(),
+                    p(&cx).children([text("footer")]).finish(),
+                ],
+            )
+        }
+    }
+
+    #[derive(Default)]
+    struct Term(&'static str);
+
+    impl Render for Term {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            fragment(
+                &cx,
+                [
+                    dt(&cx).children([text(self.0)]).finish(),
+                    dd(&cx).children([text("definition")]).finish(),
+                ],
+            )
+        }
+    }
+
+    #[test]
+    fn fragments() {
+        let html = |items: &[u32]| format!("{}<p>footer</p>", items_html(items));
+
+        let (container, vdom) = mount(Split(vec![1, 2, 3], 1));
+        assert_eq!(container.inner_html(), html(&[1, 2, 3]));
+        let ul = container.first_child().unwrap();
+        let before = ul.child_nodes();
+
+        // Keyed children are matched across fragment boundaries.
+        vdom.immediately_render_and_diff(Split(vec![3, 1, 2], 2));
+        assert_eq!(container.inner_html(), html(&[3, 1, 2]));
+        assert_eq!(container.first_child().unwrap(), ul);
+        assert_eq!(
+            ul.child_nodes(),
+            vec![before[2].clone(), before[0].clone(), before[1].clone()]
+        );
+
+        // Cached fragments are spliced into their parent, too.
+        let term = |t| Cached::new(Term(t));
+        vdom.immediately_render_and_diff(Div(vec![term("a"), term("b")]));
+        assert_eq!(
+            container.inner_html(),
+            "<div><dt>a</dt><dd>definition</dd><dt>b</dt><dd>definition</dd></div>"
+        );
+        vdom.immediately_render_and_diff(Div(vec![term("c")]));
+        assert_eq!(
+            container.inner_html(),
+            "<div><dt>c</dt><dd>definition</dd></div>"
+        );
+    }
+
+    // The wrapper's tag name, and the markup.
+    struct Help(&'static str, &'static str);
+
+    impl Render for Help {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            section(&cx)
+                .children([
+                    h2(&cx).children([text("Help")]).finish(),
+                    raw_html_in(self.0, None, self.1),
+                ])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn raw_html_is_only_set_when_it_changes() {
+        let (container, vdom) = mount(Help("div", "<p>Press <kbd>?</kbd></p>"));
+        let html = |wrapper, markup| {
+            format!(
+                "<section><h2>Help</h2><{0}>{1}</{0}></section>",
+                wrapper, markup
+            )
+        };
+        assert_eq!(
+            container.inner_html(),
+            html("div", "<p>Press <kbd>?</kbd></p>")
+        );
+
+        for &(wrapper, markup, creates, sets) in &[
+            ("div", "<p>Press <kbd>?</kbd></p>", 0, 0),
+            ("div", "<p>Press <kbd>h</kbd></p>", 0, 1),
+            // A different wrapper replaces the old one.
+            ("span", "<kbd>h</kbd>", 1, 1),
+        ] {
+            vdom.immediately_render_and_diff(Help(wrapper, markup));
+            assert_eq!(container.inner_html(), html(wrapper, markup));
+            assert_eq!(instruction_count(&vdom, "create_element"), creates);
+            assert_eq!(instruction_count(&vdom, "set_inner_html"), sets);
+        }
+    }
+
+    struct Notice(bool);
+
+    impl Render for Notice {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let banner = if self.0 {
+                Some(p(&cx).children([text("banner")]).finish())
+            } else {
+                None
+            };
+            div(&cx)
+                .children([banner.into(), text("content"), none()])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn empty_nodes_hold_their_place() {
+        let (container, vdom) = mount(Notice(false));
+        assert_eq!(
+            container.inner_html(),
+            "<div><!--none-->content<!--none--></div>"
+        );
+        let content = container.first_child().unwrap().child_nodes()[1].clone();
+
+        vdom.immediately_render_and_diff(Notice(true));
+        assert_eq!(
+            container.inner_html(),
+            "<div><p>banner</p>content<!--none--></div>"
+        );
+        vdom.immediately_render_and_diff(Notice(false));
+        assert_eq!(
+            container.inner_html(),
+            "<div><!--none-->content<!--none--></div>"
+        );
+
+        // The siblings of the toggled child were left alone.
+        assert_eq!(container.first_child().unwrap().child_nodes()[1], content);
+    }
+
+    // A controlled text input, and an optional checkbox.
+    struct Form(&'static str, Option<bool>);
+
+    impl Render for Form {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let checkbox = input(&cx).attr("type", "checkbox");
+            let checkbox = match self.1 {
+                Some(checked) => checkbox.prop("checked", checked),
+                None => checkbox,
+            };
+            form(&cx)
+                .children([input(&cx).prop("value", self.0).finish(), checkbox.finish()])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn properties_are_compared_against_the_live_dom() {
+        use crate::OwnedPropertyValue::{Bool, String};
+
+        let (container, vdom) = mount(Form("hello", Some(true)));
+        let children = container.first_child().unwrap().child_nodes();
+        let (text_input, checkbox) = (&children[0], &children[1]);
+        assert_eq!(
+            text_input.get_property("value"),
+            Some(String("hello".into()))
+        );
+        assert_eq!(checkbox.get_property("checked"), Some(Bool(true)));
+
+        // Properties are not attributes.
+        assert_eq!(
+            container.inner_html(),
+            "<form><input></input><input type=\"checkbox\"></input></form>"
+        );
+
+        // The user types and clicks, and then we re-render with the same
+        // virtual DOM, which puts the controlled properties back.
+        text_input.set_property("value", String("hello!".into()));
+        checkbox.set_property("checked", Bool(false));
+        vdom.immediately_render_and_diff(Form("hello", Some(true)));
+        assert_eq!(
+            text_input.get_property("value"),
+            Some(String("hello".into()))
+        );
+        assert_eq!(checkbox.get_property("checked"), Some(Bool(true)));
+
+        vdom.immediately_render_and_diff(Form("hello", None));
+        assert_eq!(checkbox.get_property("checked"), None);
+    }
+
+    // Like `Form`, but controlled with attributes instead of properties.
+    struct AttrForm(&'static str, bool);
+
+    impl Render for AttrForm {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            form(&cx)
+                .children([
+                    input(&cx).attr("value", self.0).finish(),
+                    input(&cx)
+                        .attr("type", "checkbox")
+                        .bool_attr("checked", self.1)
+                        .finish(),
+                ])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn volatile_attributes_update_the_live_dom() {
+        use crate::OwnedPropertyValue::{Bool, String};
+
+        let (container, vdom) = mount(AttrForm("hello", true));
+        let children = container.first_child().unwrap().child_nodes();
+        let (text_input, checkbox) = (&children[0], &children[1]);
+
+        // The user types and clicks, and then we re-render with the same
+        // virtual DOM, which puts the controlled values back.
+        text_input.set_property("value", String("hello!".into()));
+        checkbox.set_property("checked", Bool(false));
+        vdom.immediately_render_and_diff(AttrForm("hello", true));
+        assert_eq!(
+            text_input.get_property("value"),
+            Some(String("hello".into()))
+        );
+        assert_eq!(checkbox.get_property("checked"), Some(Bool(true)));
+
+        vdom.immediately_render_and_diff(AttrForm("bye", false));
+        assert_eq!(text_input.get_property("value"), Some(String("bye".into())));
+        assert_eq!(checkbox.get_property("checked"), Some(Bool(false)));
+    }
+
+    struct Styled(&'static [(&'static str, &'static str)]);
+
+    impl Render for Styled {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let mut el = div(&cx);
+            for &(property, value) in self.0 {
+                el = el.style(property, value);
+            }
+            el.finish()
+        }
+    }
+
+    #[test]
+    fn styles_are_diffed_property_by_property() {
+        let (container, vdom) = mount(Styled(&[("color", "red"), ("width", "10px")]));
+        assert_eq!(
+            container.inner_html(),
+            crate::render_to_string(&Styled(&[("color", "red"), ("width", "10px")]))
+        );
+
+        vdom.immediately_render_and_diff(Styled(&[("color", "blue"), ("width", "10px")]));
+        let div = container.first_child().unwrap();
+        assert_eq!(div.style_property("color").as_deref(), Some("blue"));
+        assert_eq!(instruction_count(&vdom, "set_style_property"), 1);
+        assert_eq!(instruction_count(&vdom, "set_attribute"), 0);
+
+        vdom.immediately_render_and_diff(Styled(&[("width", "10px")]));
+        assert_eq!(div.get_attribute("style").as_deref(), Some("width: 10px;"));
+        assert_eq!(instruction_count(&vdom, "remove_style_property"), 1);
+        assert_eq!(instruction_count(&vdom, "set_style_property"), 0);
+    }
+
+    struct Todo {
+        completed: bool,
+        editing: bool,
+    }
+
+    impl Render for Todo {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            li(&cx)
+                .class("todo")
+                .class_if("completed", self.completed)
+                .class_if("editing", self.editing)
+                .finish()
+        }
+    }
+
+    #[test]
+    fn classes_are_diffed_token_by_token() {
+        let todo = |completed, editing| Todo { completed, editing };
+        let (container, vdom) = mount(todo(true, false));
+        assert_eq!(container.inner_html(), "<li class=\"todo completed\"></li>");
+        assert_eq!(
+            container.inner_html(),
+            crate::render_to_string(&todo(true, false))
+        );
+
+        vdom.immediately_render_and_diff(todo(true, true));
+        let li = container.first_child().unwrap();
+        assert_eq!(li.class_list(), ["todo", "completed", "editing"]);
+        assert_eq!(instruction_count(&vdom, "add_class"), 1);
+        assert_eq!(instruction_count(&vdom, "remove_class"), 0);
+        assert_eq!(instruction_count(&vdom, "set_class"), 0);
+
+        vdom.immediately_render_and_diff(todo(false, true));
+        assert_eq!(li.class_list(), ["todo", "editing"]);
+        assert_eq!(instruction_count(&vdom, "remove_class"), 1);
+        assert_eq!(instruction_count(&vdom, "add_class"), 0);
+    }
+
+    struct Button(&'static str);
+
+    impl Render for Button {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            button(&cx).class(self.0).class("").finish()
+        }
+    }
+
+    #[test]
+    fn class_names_are_split_on_whitespace() {
+        let (container, vdom) = mount(Button("btn  primary"));
+        assert_eq!(
+            container.inner_html(),
+            "<button class=\"btn primary\"></button>"
+        );
+        assert_eq!(
+            container.inner_html(),
+            crate::render_to_string(&Button("btn  primary"))
+        );
+
+        vdom.immediately_render_and_diff(Button(" btn "));
+        let button = container.first_child().unwrap();
+        assert_eq!(button.class_list(), ["btn"]);
+        assert_eq!(instruction_count(&vdom, "remove_class"), 1);
+        assert_eq!(instruction_count(&vdom, "add_class"), 0);
+    }
+}
@@ -99,7 +99,10 @@ cfg_if::cfg_if! {
 
             pub(crate) fn remove_subtree(&mut self, node: &Node) {
                 match node.kind {
-                    NodeKind::Cached(_) | NodeKind::Text(_) => return,
+                    NodeKind::Cached(_)
+                    | NodeKind::Text(_)
+                    | NodeKind::RawHtml(_)
+                    | NodeKind::Empty => return,
                     NodeKind::Element(&ElementNode {listeners, children, ..}) => {
                         for l in listeners {
                             self.remove(l);
@@ -108,6 +111,12 @@ cfg_if::cfg_if! {
                             self.remove_subtree(child)
                         }
                     }
+                    NodeKind::Portal(portal) => self.remove_subtree(&portal.child),
+                    NodeKind::Fragment(fragment) => {
+                        for child in fragment.children {
+                            self.remove_subtree(child)
+                        }
+                    }
                 }
             }
 
@@ -0,0 +1,485 @@
+//! A Rust implementation of `js/change-list-interpreter.js` that applies change
+//! lists to the headless DOM.
+//!
+//! Each instruction's implementation mirrors the corresponding function in the
+//! JS interpreter's `OP_TABLE`, and their semantics must be kept in sync.
+
+use super::{Element, Node};
+use crate::{change_list::disassembler::Instruction, OwnedPropertyValue};
+use fxhash::FxHashMap;
+
+/// Applies change list instructions to a headless DOM container.
+#[derive(Debug)]
+pub struct ChangeListInterpreter {
+    container: Element,
+    stack: Vec<Node>,
+    strings: FxHashMap<u32, String>,
+    temporaries: Vec<Option<Node>>,
+    templates: FxHashMap<u32, Node>,
+    portal_targets: FxHashMap<u32, Element>,
+    // Maps each portal's placeholder comment to the portal's children.
+    portals: Vec<(Node, Vec<Node>)>,
+    // Maps each attached node ref's id to its element.
+    node_refs: FxHashMap<u32, Element>,
+}
+
+impl ChangeListInterpreter {
+    /// Construct a new interpreter that applies changes to the given
+    /// container's children.
+    pub fn new(container: &Element) -> ChangeListInterpreter {
+        ChangeListInterpreter {
+            container: container.clone(),
+            stack: vec![],
+            strings: FxHashMap::default(),
+            temporaries: vec![],
+            templates: FxHashMap::default(),
+            portal_targets: FxHashMap::default(),
+            portals: vec![],
+            node_refs: FxHashMap::default(),
+        }
+    }
+
+    /// Forget all cached strings and templates, remove all portal children
+    /// from their targets, and clear all node refs.
+    pub fn unmount(&mut self) {
+        for (_, children) in self.portals.drain(..) {
+            for child in children {
+                child.remove();
+            }
+        }
+        for (id, _) in self.node_refs.drain() {
+            crate::node_ref::set_element(id, None);
+        }
+        self.stack.clear();
+        self.strings.clear();
+        self.temporaries.clear();
+        self.templates.clear();
+    }
+
+    /// Listeners are recorded on the headless elements but never invoked, so
+    /// there is no trampoline to initialize.
+    pub fn init_events_trampoline(&self, _trampoline: &crate::EventsTrampoline) {}
+
+    /// Node refs are set directly, so there is no trampoline to initialize.
+    pub fn init_node_refs_trampoline(&self, _trampoline: &crate::NodeRefsTrampoline) {}
+
+    /// Apply the given decoded instructions.
+    pub(crate) fn apply_changes(&mut self, instructions: &[Instruction]) {
+        if instructions.is_empty() {
+            return;
+        }
+
+        self.stack.push(self.container.clone());
+
+        for instruction in instructions {
+            self.apply(instruction);
+        }
+
+        self.stack.clear();
+        self.temporaries.clear();
+
+        self.remove_detached_portals();
+    }
+
+    /// Register the element with the given id as a portal target.
+    pub fn add_portal_target(&mut self, id: u32, target: &Element) {
+        self.portal_targets.insert(id, target.clone());
+    }
+
+    // Remove the children of portals whose placeholders were removed from the
+    // DOM by the last change list. A placeholder is still attached if it is
+    // inside our container, or inside the child of another attached portal.
+    fn remove_detached_portals(&mut self) {
+        let mut attached = vec![false; self.portals.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..self.portals.len() {
+                if attached[i] {
+                    continue;
+                }
+                let placeholder = &self.portals[i].0;
+                let is_attached = self.container.contains(placeholder)
+                    || self
+                        .portals
+                        .iter()
+                        .zip(&attached)
+                        .any(|((_, children), &a)| {
+                            a && children.iter().any(|child| child.contains(placeholder))
+                        });
+                if is_attached {
+                    attached[i] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut attached = attached.into_iter();
+        let mut detached = vec![];
+        self.portals.retain(|(_, children)| {
+            let keep = attached.next().unwrap();
+            if !keep {
+                for child in children {
+                    child.remove();
+                    detached.push(child.clone());
+                }
+            }
+            keep
+        });
+        for child in detached {
+            self.clear_node_refs_in(&child);
+        }
+    }
+
+    // Clear the node refs that are attached to `node` or its descendants,
+    // because `node` is being removed.
+    fn clear_node_refs_in(&mut self, node: &Node) {
+        self.node_refs.retain(|&id, element| {
+            let keep = !node.contains(element);
+            if !keep {
+                crate::node_ref::set_element(id, None);
+            }
+            keep
+        });
+    }
+
+    fn top(&self) -> &Node {
+        self.stack
+            .last()
+            .expect("change list stack should not be empty")
+    }
+
+    fn pop(&mut self) -> Node {
+        self.stack
+            .pop()
+            .expect("change list stack should not be empty")
+    }
+
+    fn get_cached_string(&self, id: u32) -> &str {
+        self.strings
+            .get(&id)
+            .map(|s| &s[..])
+            .expect("change list should only reference cached strings that exist")
+    }
+
+    fn child(parent: &Node, n: u32) -> Node {
+        parent
+            .child_nodes()
+            .get(n as usize)
+            .cloned()
+            .expect("change list should only reference children that exist")
+    }
+
+    fn reverse_child(parent: &Node, n: u32) -> Node {
+        let children = parent.child_nodes();
+        children[children.len() - n as usize - 1].clone()
+    }
+
+    fn apply(&mut self, instruction: &Instruction) {
+        match *instruction {
+            Instruction::SetText { ref text } => {
+                self.top().set_text_content(text);
+            }
+
+            Instruction::RemoveSelfAndNextSiblings => {
+                let node = self.pop();
+                while let Some(sibling) = node.next_sibling() {
+                    self.clear_node_refs_in(&sibling);
+                    sibling.remove();
+                }
+                self.clear_node_refs_in(&node);
+                node.remove();
+            }
+
+            Instruction::ReplaceWith => {
+                let new = self.pop();
+                let old = self.pop();
+                self.clear_node_refs_in(&old);
+                old.replace_with(&new);
+                self.stack.push(new);
+            }
+
+            Instruction::SetAttribute {
+                name_key,
+                value_key,
+            } => {
+                let name = self.get_cached_string(name_key);
+                let value = self.get_cached_string(value_key);
+                self.top().set_attribute(name, value);
+
+                // Like the JS interpreter, also set the properties behind
+                // "volatile" attributes.
+                match name {
+                    "value" => self
+                        .top()
+                        .set_property(name, OwnedPropertyValue::String(value.to_string())),
+                    "checked" | "selected" => self
+                        .top()
+                        .set_property(name, OwnedPropertyValue::Bool(true)),
+                    _ => {}
+                }
+            }
+
+            Instruction::RemoveAttribute { name_key } => {
+                let name = self.get_cached_string(name_key);
+                self.top().remove_attribute(name);
+
+                match name {
+                    "value" => self
+                        .top()
+                        .set_property(name, OwnedPropertyValue::String(String::new())),
+                    "checked" | "selected" => self
+                        .top()
+                        .set_property(name, OwnedPropertyValue::Bool(false)),
+                    _ => {}
+                }
+            }
+
+            Instruction::PushReverseChild { n } => {
+                let child = Self::reverse_child(self.top(), n);
+                self.stack.push(child);
+            }
+
+            Instruction::PopPushChild { n } => {
+                self.pop();
+                let child = Self::child(self.top(), n);
+                self.stack.push(child);
+            }
+
+            Instruction::Pop => {
+                self.pop();
+            }
+
+            Instruction::AppendChild => {
+                let child = self.pop();
+                self.top().append_child(&child);
+            }
+
+            Instruction::CreateTextNode { ref text } => {
+                self.stack.push(Node::create_text_node(text));
+            }
+
+            Instruction::CreateElement { tag_name_key } => {
+                let el = Node::create_element(self.get_cached_string(tag_name_key));
+                self.stack.push(el);
+            }
+
+            Instruction::NewEventListener { event_key, a, b }
+            | Instruction::UpdateEventListener { event_key, a, b } => {
+                let event = self.get_cached_string(event_key);
+                self.top().set_listener(event, a, b);
+            }
+
+            Instruction::RemoveEventListener { event_key } => {
+                let event = self.get_cached_string(event_key);
+                self.top().remove_listener(event);
+            }
+
+            Instruction::AddCachedString { ref string, key } => {
+                self.strings.insert(key, string.clone());
+            }
+
+            Instruction::DropCachedString { key } => {
+                self.strings.remove(&key);
+            }
+
+            Instruction::CreateElementNs {
+                tag_name_key,
+                namespace_key,
+            } => {
+                let tag_name = self.get_cached_string(tag_name_key);
+                let namespace = self.get_cached_string(namespace_key);
+                let el = Node::create_element_ns(namespace, tag_name);
+                self.stack.push(el);
+            }
+
+            Instruction::SaveChildrenToTemporaries {
+                temp_base,
+                start,
+                end,
+            } => {
+                let children = self.top().child_nodes();
+                let children = &children[start as usize..end as usize];
+                let end = temp_base as usize + children.len();
+                if self.temporaries.len() < end {
+                    self.temporaries.resize(end, None);
+                }
+                for (temp, child) in (temp_base as usize..).zip(children) {
+                    self.temporaries[temp] = Some(child.clone());
+                }
+            }
+
+            Instruction::PushChild { n } => {
+                let child = Self::child(self.top(), n);
+                self.stack.push(child);
+            }
+
+            Instruction::PushTemporary { temp } => {
+                let temp = self.temporaries[temp as usize]
+                    .clone()
+                    .expect("change list should only push temporaries that were saved");
+                self.stack.push(temp);
+            }
+
+            Instruction::InsertBefore => {
+                let before = self.pop();
+                let after = self.pop();
+                let parent = after
+                    .parent_node()
+                    .expect("`insert_before` target should have a parent");
+                parent.insert_before(&before, Some(&after));
+                self.stack.push(before);
+            }
+
+            Instruction::PopPushReverseChild { n } => {
+                self.pop();
+                let child = Self::reverse_child(self.top(), n);
+                self.stack.push(child);
+            }
+
+            Instruction::RemoveChild { n } => {
+                let child = Self::child(self.top(), n);
+                self.clear_node_refs_in(&child);
+                child.remove();
+            }
+
+            Instruction::SetClass { class_key } => {
+                let class = self.get_cached_string(class_key);
+                self.top().set_attribute("class", class);
+            }
+
+            Instruction::SaveTemplate { id } => {
+                let template = self.top().clone_node(true);
+                self.templates.insert(id, template);
+            }
+
+            Instruction::PushTemplate { id } => {
+                let template = self
+                    .templates
+                    .get(&id)
+                    .expect("change list should only push templates that were saved")
+                    .clone_node(true);
+                self.stack.push(template);
+            }
+
+            Instruction::CreateComment { ref text } => {
+                self.stack.push(Node::create_comment(text));
+            }
+
+            Instruction::PushPortalChild { index } => {
+                let child = self
+                    .portals
+                    .iter()
+                    .find(|(placeholder, _)| placeholder == self.top())
+                    .and_then(|(_, children)| children.get(index as usize))
+                    .cloned()
+                    .expect("change list should only push children of mounted portals");
+                self.stack.push(child);
+            }
+
+            Instruction::MountPortal { target_id, index } => {
+                let target = self
+                    .portal_targets
+                    .get(&target_id)
+                    .expect("change list should only mount portals into known targets")
+                    .clone();
+                let child = self.pop();
+                let placeholder = self.top().clone();
+                let i = match self.portals.iter().position(|(p, _)| *p == placeholder) {
+                    Some(i) => i,
+                    None => {
+                        self.portals.push((placeholder, vec![]));
+                        self.portals.len() - 1
+                    }
+                };
+                let children = &mut self.portals[i].1;
+                let index = index as usize;
+                if child.parent_node().as_ref() != Some(&target) {
+                    // Keep the portal's children in order.
+                    let next = if index > 0 {
+                        children[index - 1].next_sibling()
+                    } else {
+                        None
+                    };
+                    target.insert_before(&child, next.as_ref());
+                }
+                if index < children.len() {
+                    children[index] = child;
+                } else {
+                    debug_assert_eq!(index, children.len());
+                    children.push(child);
+                }
+            }
+
+            Instruction::TruncatePortal { len } => {
+                let placeholder = self.top().clone();
+                let removed = self
+                    .portals
+                    .iter_mut()
+                    .find(|(p, _)| *p == placeholder)
+                    .map(|(_, children)| children.split_off(len as usize))
+                    .expect("change list should only truncate mounted portals");
+                for child in removed {
+                    child.remove();
+                    self.clear_node_refs_in(&child);
+                }
+            }
+
+            Instruction::SetNodeRef { id } => {
+                let element = self.top().clone();
+                self.node_refs.insert(id, element.clone());
+                crate::node_ref::set_element(id, Some(element));
+            }
+
+            Instruction::RemoveNodeRef { id } => {
+                if self.node_refs.get(&id) == Some(self.top()) {
+                    self.node_refs.remove(&id);
+                    crate::node_ref::set_element(id, None);
+                }
+            }
+
+            Instruction::SetInnerHtml { ref html } => {
+                self.top().set_inner_html(html);
+            }
+
+            Instruction::SetProperty {
+                name_key,
+                ref value,
+            } => {
+                let name = self.get_cached_string(name_key);
+                if self.top().get_property(name).as_ref() != Some(value) {
+                    self.top().set_property(name, value.clone());
+                }
+            }
+
+            Instruction::RemoveProperty { name_key } => {
+                let name = self.get_cached_string(name_key);
+                self.top().remove_property(name);
+            }
+
+            Instruction::SetStyleProperty {
+                name_key,
+                value_key,
+            } => {
+                let name = self.get_cached_string(name_key);
+                let value = self.get_cached_string(value_key);
+                self.top().set_style_property(name, value);
+            }
+
+            Instruction::RemoveStyleProperty { name_key } => {
+                let name = self.get_cached_string(name_key);
+                self.top().remove_style_property(name);
+            }
+
+            Instruction::AddClass { class_key } => {
+                let class = self.get_cached_string(class_key);
+                self.top().add_class(class);
+            }
+
+            Instruction::RemoveClass { class_key } => {
+                let class = self.get_cached_string(class_key);
+                self.top().remove_class(class);
+            }
+        }
+    }
+}
@@ -0,0 +1,598 @@
+//! A headless, in-memory DOM.
+//!
+//! When built natively with the `xxx-unstable-internal-use-only` feature, this
+//! stands in for the physical DOM so that a `Vdom` can be mounted, rendered,
+//! diffed and inspected without a browser. Its API loosely mirrors the subset of
+//! `web_sys` that the change list interpreter uses.
+//!
+//! ## Example
+//!
+//! ```
+//! use dodrio::{headless::Element, Node, Render, RenderContext, Vdom};
+//!
+//! struct Hello;
+//!
+//! impl Render for Hello {
+//!     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+//!         use dodrio::builder::*;
+//!         p(&cx).children([text("Hello!")]).finish()
+//!     }
+//! }
+//!
+//! let container = Element::create_element("div");
+//! let vdom = Vdom::new(&container, Hello);
+//! assert_eq!(container.inner_html(), "<p>Hello!</p>");
+//! ```
+
+mod interpreter;
+#[cfg(test)]
+pub(crate) mod testing;
+
+pub use self::interpreter::ChangeListInterpreter;
+
+use crate::OwnedPropertyValue;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+/// A node in the headless DOM: an element, a text node, or a comment.
+///
+/// This is a cheap, reference-counted handle. Cloning it does not clone the
+/// underlying node; use `clone_node` for that.
+#[derive(Clone)]
+pub struct Node {
+    inner: Rc<RefCell<NodeData>>,
+}
+
+/// An element node in the headless DOM.
+pub type Element = Node;
+
+struct NodeData {
+    parent: Option<Weak<RefCell<NodeData>>>,
+    children: Vec<Node>,
+    kind: NodeDataKind,
+}
+
+enum NodeDataKind {
+    Element {
+        tag_name: String,
+        namespace: Option<String>,
+        attributes: Vec<(String, String)>,
+        properties: Vec<(String, OwnedPropertyValue)>,
+        listeners: Vec<ListenerData>,
+    },
+    Text(String),
+    Comment(String),
+    // Markup that was set with `set_inner_html`, which is kept unparsed.
+    Markup(String),
+}
+
+#[derive(Clone, Debug)]
+struct ListenerData {
+    event: String,
+    a: u32,
+    b: u32,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for Node {}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Node").field(&self.outer_html()).finish()
+    }
+}
+
+impl Node {
+    fn new(kind: NodeDataKind) -> Node {
+        Node {
+            inner: Rc::new(RefCell::new(NodeData {
+                parent: None,
+                children: vec![],
+                kind,
+            })),
+        }
+    }
+
+    /// Create a new, detached element with the given tag name.
+    pub fn create_element(tag_name: &str) -> Element {
+        Node::new(NodeDataKind::Element {
+            tag_name: tag_name.to_string(),
+            namespace: None,
+            attributes: vec![],
+            properties: vec![],
+            listeners: vec![],
+        })
+    }
+
+    /// Create a new, detached element with the given namespace and tag name.
+    pub fn create_element_ns(namespace: &str, tag_name: &str) -> Element {
+        Node::new(NodeDataKind::Element {
+            tag_name: tag_name.to_string(),
+            namespace: Some(namespace.to_string()),
+            attributes: vec![],
+            properties: vec![],
+            listeners: vec![],
+        })
+    }
+
+    /// Create a new, detached text node.
+    pub fn create_text_node(text: &str) -> Node {
+        Node::new(NodeDataKind::Text(text.to_string()))
+    }
+
+    /// Create a new, detached comment.
+    pub fn create_comment(data: &str) -> Node {
+        Node::new(NodeDataKind::Comment(data.to_string()))
+    }
+
+    /// Is this an element?
+    pub fn is_element(&self) -> bool {
+        matches!(self.inner.borrow().kind, NodeDataKind::Element { .. })
+    }
+
+    /// Is this a text node?
+    pub fn is_text(&self) -> bool {
+        matches!(self.inner.borrow().kind, NodeDataKind::Text(_))
+    }
+
+    /// Is this a comment?
+    pub fn is_comment(&self) -> bool {
+        matches!(self.inner.borrow().kind, NodeDataKind::Comment(_))
+    }
+
+    /// This element's tag name, or `None` if this is not an element.
+    pub fn tag_name(&self) -> Option<String> {
+        match self.inner.borrow().kind {
+            NodeDataKind::Element { ref tag_name, .. } => Some(tag_name.clone()),
+            _ => None,
+        }
+    }
+
+    /// This element's namespace, or `None` if it is an HTML element or not an
+    /// element at all.
+    pub fn namespace_uri(&self) -> Option<String> {
+        match self.inner.borrow().kind {
+            NodeDataKind::Element { ref namespace, .. } => namespace.clone(),
+            _ => None,
+        }
+    }
+
+    /// Get this node's parent, if it is attached to one.
+    pub fn parent_node(&self) -> Option<Node> {
+        let inner = self.inner.borrow();
+        let parent = inner.parent.as_ref()?.upgrade()?;
+        Some(Node { inner: parent })
+    }
+
+    /// Get this node's children.
+    pub fn child_nodes(&self) -> Vec<Node> {
+        self.inner.borrow().children.clone()
+    }
+
+    /// Get this node's first child, if any.
+    pub fn first_child(&self) -> Option<Node> {
+        self.inner.borrow().children.first().cloned()
+    }
+
+    /// Get the sibling immediately after this node, if any.
+    pub fn next_sibling(&self) -> Option<Node> {
+        let parent = self.parent_node()?;
+        let index = self.index_in(&parent);
+        let parent = parent.inner.borrow();
+        parent.children.get(index + 1).cloned()
+    }
+
+    /// Is `other` this node or one of its descendants?
+    pub fn contains(&self, other: &Node) -> bool {
+        let mut node = Some(other.clone());
+        while let Some(n) = node {
+            if n == *self {
+                return true;
+            }
+            node = n.parent_node();
+        }
+        false
+    }
+
+    fn index_in(&self, parent: &Node) -> usize {
+        parent
+            .inner
+            .borrow()
+            .children
+            .iter()
+            .position(|c| c == self)
+            .expect("a node should be one of its parent's children")
+    }
+
+    /// Detach this node from its parent, if it has one.
+    pub fn remove(&self) {
+        if let Some(parent) = self.parent_node() {
+            let index = self.index_in(&parent);
+            parent.inner.borrow_mut().children.remove(index);
+        }
+        self.inner.borrow_mut().parent = None;
+    }
+
+    /// Append `child` to this node's children, first detaching it from its
+    /// current parent.
+    pub fn append_child(&self, child: &Node) {
+        child.remove();
+        child.inner.borrow_mut().parent = Some(Rc::downgrade(&self.inner));
+        self.inner.borrow_mut().children.push(child.clone());
+    }
+
+    /// Insert `child` into this node's children before `reference`, or at the
+    /// end if `reference` is `None`. `child` is first detached from its current
+    /// parent.
+    pub fn insert_before(&self, child: &Node, reference: Option<&Node>) {
+        if reference == Some(child) {
+            return;
+        }
+        child.remove();
+        let index = match reference {
+            Some(reference) => reference.index_in(self),
+            None => self.inner.borrow().children.len(),
+        };
+        child.inner.borrow_mut().parent = Some(Rc::downgrade(&self.inner));
+        self.inner
+            .borrow_mut()
+            .children
+            .insert(index, child.clone());
+    }
+
+    /// Replace this node with `new` in this node's parent.
+    pub fn replace_with(&self, new: &Node) {
+        if self == new {
+            return;
+        }
+        if let Some(parent) = self.parent_node() {
+            new.remove();
+            let index = self.index_in(&parent);
+            new.inner.borrow_mut().parent = Some(Rc::downgrade(&parent.inner));
+            parent.inner.borrow_mut().children[index] = new.clone();
+            self.inner.borrow_mut().parent = None;
+        }
+    }
+
+    /// Create a copy of this node, and all of its descendants if `deep` is
+    /// true.
+    ///
+    /// Like the real DOM, event listeners are not copied.
+    pub fn clone_node(&self, deep: bool) -> Node {
+        let kind = match self.inner.borrow().kind {
+            NodeDataKind::Element {
+                ref tag_name,
+                ref namespace,
+                ref attributes,
+                ..
+            } => NodeDataKind::Element {
+                tag_name: tag_name.clone(),
+                namespace: namespace.clone(),
+                attributes: attributes.clone(),
+                properties: vec![],
+                listeners: vec![],
+            },
+            NodeDataKind::Text(ref text) => NodeDataKind::Text(text.clone()),
+            NodeDataKind::Comment(ref data) => NodeDataKind::Comment(data.clone()),
+            NodeDataKind::Markup(ref html) => NodeDataKind::Markup(html.clone()),
+        };
+        let clone = Node::new(kind);
+        if deep {
+            for child in self.child_nodes() {
+                clone.append_child(&child.clone_node(true));
+            }
+        }
+        clone
+    }
+
+    /// Get the text content of this node and all of its descendants.
+    ///
+    /// For comments, this is the comment's data.
+    pub fn text_content(&self) -> String {
+        if let NodeDataKind::Comment(ref data) = self.inner.borrow().kind {
+            return data.clone();
+        }
+        let mut text = String::new();
+        self.collect_text(&mut text);
+        text
+    }
+
+    fn collect_text(&self, out: &mut String) {
+        let inner = self.inner.borrow();
+        match inner.kind {
+            NodeDataKind::Text(ref text) => out.push_str(text),
+            // Collecting the text inside markup would require parsing it.
+            NodeDataKind::Comment(_) | NodeDataKind::Markup(_) => {}
+            NodeDataKind::Element { .. } => {
+                for child in &inner.children {
+                    child.collect_text(out);
+                }
+            }
+        }
+    }
+
+    /// Set this node's text content.
+    ///
+    /// For elements, this replaces all children with a single text node, or
+    /// with nothing if `text` is empty.
+    pub fn set_text_content(&self, text: &str) {
+        match self.inner.borrow_mut().kind {
+            NodeDataKind::Text(ref mut data)
+            | NodeDataKind::Comment(ref mut data)
+            | NodeDataKind::Markup(ref mut data) => {
+                *data = text.to_string();
+                return;
+            }
+            NodeDataKind::Element { .. } => {}
+        }
+        for child in self.child_nodes() {
+            child.remove();
+        }
+        if !text.is_empty() {
+            self.append_child(&Node::create_text_node(text));
+        }
+    }
+
+    /// Set this element's inner HTML.
+    ///
+    /// The headless DOM doesn't parse HTML, so this replaces all children with
+    /// a single opaque node that is serialized back out verbatim.
+    pub fn set_inner_html(&self, html: &str) {
+        for child in self.child_nodes() {
+            child.remove();
+        }
+        if !html.is_empty() {
+            self.append_child(&Node::new(NodeDataKind::Markup(html.to_string())));
+        }
+    }
+
+    /// Get the value of the attribute with the given name.
+    pub fn get_attribute(&self, name: &str) -> Option<String> {
+        match self.inner.borrow().kind {
+            NodeDataKind::Element { ref attributes, .. } => attributes
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the names of all of this element's attributes, in the order they
+    /// were added.
+    pub fn get_attribute_names(&self) -> Vec<String> {
+        match self.inner.borrow().kind {
+            NodeDataKind::Element { ref attributes, .. } => {
+                attributes.iter().map(|(n, _)| n.clone()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Set the attribute with the given name.
+    pub fn set_attribute(&self, name: &str, value: &str) {
+        if let NodeDataKind::Element {
+            ref mut attributes, ..
+        } = self.inner.borrow_mut().kind
+        {
+            match attributes.iter_mut().find(|(n, _)| n == name) {
+                Some(attr) => attr.1 = value.to_string(),
+                None => attributes.push((name.to_string(), value.to_string())),
+            }
+        }
+    }
+
+    /// Remove the attribute with the given name.
+    pub fn remove_attribute(&self, name: &str) {
+        if let NodeDataKind::Element {
+            ref mut attributes, ..
+        } = self.inner.borrow_mut().kind
+        {
+            attributes.retain(|(n, _)| n != name);
+        }
+    }
+
+    /// Get the value of the property with the given name, if it has been set.
+    pub fn get_property(&self, name: &str) -> Option<OwnedPropertyValue> {
+        match self.inner.borrow().kind {
+            NodeDataKind::Element { ref properties, .. } => properties
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Set the property with the given name, like the user would by
+    /// interacting with the element.
+    pub fn set_property(&self, name: &str, value: OwnedPropertyValue) {
+        if let NodeDataKind::Element {
+            ref mut properties, ..
+        } = self.inner.borrow_mut().kind
+        {
+            match properties.iter_mut().find(|(n, _)| n == name) {
+                Some(prop) => prop.1 = value,
+                None => properties.push((name.to_string(), value)),
+            }
+        }
+    }
+
+    /// Unset the property with the given name.
+    pub fn remove_property(&self, name: &str) {
+        if let NodeDataKind::Element {
+            ref mut properties, ..
+        } = self.inner.borrow_mut().kind
+        {
+            properties.retain(|(n, _)| n != name);
+        }
+    }
+
+    /// Get the value of the inline style property with the given name.
+    pub fn style_property(&self, name: &str) -> Option<String> {
+        let style = self.get_attribute("style")?;
+        parse_style(&style)
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Set an inline style property, updating the `style` attribute like
+    /// browsers do.
+    pub fn set_style_property(&self, name: &str, value: &str) {
+        let mut declarations = parse_style(&self.get_attribute("style").unwrap_or_default());
+        match declarations.iter_mut().find(|(n, _)| n == name) {
+            Some(declaration) => declaration.1 = value.to_string(),
+            None => declarations.push((name.to_string(), value.to_string())),
+        }
+        self.set_style(&declarations);
+    }
+
+    /// Remove an inline style property, updating the `style` attribute like
+    /// browsers do.
+    pub fn remove_style_property(&self, name: &str) {
+        let mut declarations = parse_style(&self.get_attribute("style").unwrap_or_default());
+        declarations.retain(|(n, _)| n != name);
+        self.set_style(&declarations);
+    }
+
+    fn set_style(&self, declarations: &[(String, String)]) {
+        let style = declarations
+            .iter()
+            .map(|(name, value)| format!("{}: {};", name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.set_attribute("style", &style);
+    }
+
+    /// Get the classes in this element's `class` attribute.
+    pub fn class_list(&self) -> Vec<String> {
+        self.get_attribute("class")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|class| class.to_string())
+            .collect()
+    }
+
+    /// Add a class, updating the `class` attribute like browsers do.
+    pub fn add_class(&self, class: &str) {
+        let mut classes = self.class_list();
+        if classes.iter().all(|c| c != class) {
+            classes.push(class.to_string());
+        }
+        self.set_attribute("class", &classes.join(" "));
+    }
+
+    /// Remove a class, updating the `class` attribute like browsers do.
+    pub fn remove_class(&self, class: &str) {
+        let mut classes = self.class_list();
+        classes.retain(|c| c != class);
+        self.set_attribute("class", &classes.join(" "));
+    }
+
+    /// Get the event types this element is listening for.
+    pub fn event_listeners(&self) -> Vec<String> {
+        match self.inner.borrow().kind {
+            NodeDataKind::Element { ref listeners, .. } => {
+                listeners.iter().map(|l| l.event.clone()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn set_listener(&self, event: &str, a: u32, b: u32) {
+        if let NodeDataKind::Element {
+            ref mut listeners, ..
+        } = self.inner.borrow_mut().kind
+        {
+            match listeners.iter_mut().find(|l| l.event == event) {
+                Some(l) => {
+                    l.a = a;
+                    l.b = b;
+                }
+                None => listeners.push(ListenerData {
+                    event: event.to_string(),
+                    a,
+                    b,
+                }),
+            }
+        }
+    }
+
+    fn remove_listener(&self, event: &str) {
+        if let NodeDataKind::Element {
+            ref mut listeners, ..
+        } = self.inner.borrow_mut().kind
+        {
+            listeners.retain(|l| l.event != event);
+        }
+    }
+
+    /// Serialize this node's children to HTML.
+    pub fn inner_html(&self) -> String {
+        let mut html = String::new();
+        for child in self.child_nodes() {
+            child.write_html(&mut html);
+        }
+        html
+    }
+
+    /// Serialize this node and its children to HTML.
+    pub fn outer_html(&self) -> String {
+        let mut html = String::new();
+        self.write_html(&mut html);
+        html
+    }
+
+    fn write_html(&self, html: &mut String) {
+        let inner = self.inner.borrow();
+        match inner.kind {
+            NodeDataKind::Text(ref text) => crate::html::escape(html, text, false),
+            NodeDataKind::Markup(ref markup) => html.push_str(markup),
+            NodeDataKind::Comment(ref data) => {
+                html.push_str("<!--");
+                html.push_str(data);
+                html.push_str("-->");
+            }
+            NodeDataKind::Element {
+                ref tag_name,
+                ref attributes,
+                ..
+            } => {
+                html.push('<');
+                html.push_str(tag_name);
+                for (name, value) in attributes {
+                    html.push(' ');
+                    html.push_str(name);
+                    html.push_str("=\"");
+                    crate::html::escape(html, value, true);
+                    html.push('"');
+                }
+                html.push('>');
+                for child in &inner.children {
+                    child.write_html(html);
+                }
+                html.push_str("</");
+                html.push_str(tag_name);
+                html.push('>');
+            }
+        }
+    }
+}
+
+// Split a `style` attribute into its `(property, value)` declarations.
+fn parse_style(style: &str) -> Vec<(String, String)> {
+    style
+        .split(';')
+        .filter_map(|declaration| {
+            let mut parts = declaration.splitn(2, ':');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
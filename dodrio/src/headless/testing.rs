@@ -0,0 +1,111 @@
+//! Fixtures shared by the tests that render into the headless DOM.
+//!
+//! Each module's tests live next to the module itself, and use these to mount
+//! components and inspect what was rendered.
+
+use super::Element;
+use crate::{builder::*, bumpalo, Cached, Node, Render, RenderContext, RootRender, Vdom};
+
+/// Mount `component` into a fresh container.
+///
+/// Render profiling is enabled, so that tests can check which instructions
+/// each subsequent render emitted with `instruction_count`.
+pub(crate) fn mount<R>(component: R) -> (Element, Vdom)
+where
+    R: RootRender,
+{
+    let container = Element::create_element("div");
+    let vdom = Vdom::new(&container, component);
+    vdom.set_render_profiling(1);
+    (container, vdom)
+}
+
+/// How many of the given instruction the last render emitted.
+pub(crate) fn instruction_count(vdom: &Vdom, instruction: &str) -> usize {
+    let profile = vdom.last_render_profile().expect("should have profiled");
+    profile
+        .instruction_counts()
+        .get(instruction)
+        .cloned()
+        .unwrap_or(0)
+}
+
+/// How many cached renders the last render reused and re-rendered.
+pub(crate) fn cache_hits_and_misses(vdom: &Vdom) -> (usize, usize) {
+    let profile = vdom.last_render_profile().expect("should have profiled");
+    (profile.cache_hits(), profile.cache_misses())
+}
+
+/// A `<ul>` of `<li class="item">item {i}</li>`s, optionally keyed by `i`.
+pub(crate) struct List {
+    items: Vec<u32>,
+    keyed: bool,
+}
+
+impl Render for List {
+    fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+        let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+        for &i in &self.items {
+            let label = bumpalo::format!(in cx.bump, "item {}", i).into_bump_str();
+            let li = li(&cx).attr("class", "item").children([text(label)]);
+            let li = if self.keyed { li.key(i) } else { li };
+            children.push(li.finish());
+        }
+        ul(&cx)
+            .on("click", |_, _, _| {})
+            .children(children)
+            .finish()
+    }
+}
+
+pub(crate) fn list(items: &[u32], keyed: bool) -> List {
+    List {
+        items: items.to_vec(),
+        keyed,
+    }
+}
+
+/// The HTML that `list(items, _)` renders.
+pub(crate) fn items_html(items: &[u32]) -> String {
+    let mut html = String::from("<ul>");
+    for i in items {
+        html.push_str(&format!("<li class=\"item\">item {}</li>", i));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// A `<p>Hello, {who}</p>` with a click listener.
+#[derive(Default)]
+pub(crate) struct Greeting {
+    pub(crate) who: String,
+}
+
+impl Render for Greeting {
+    fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+        let who = bumpalo::collections::String::from_str_in(&self.who, cx.bump);
+        p(&cx)
+            .on("click", |_, _, _| {})
+            .children([text("Hello, "), text(who.into_bump_str())])
+            .finish()
+    }
+}
+
+pub(crate) fn greeting(who: &str) -> Cached<Greeting> {
+    Cached::new(Greeting {
+        who: who.to_string(),
+    })
+}
+
+/// A `<div>` around each of its components.
+pub(crate) struct Div<T>(pub(crate) Vec<T>);
+
+impl<T: Render> Render for Div<T> {
+    fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+        let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+        for child in &self.0 {
+            children.push(child.render(cx));
+        }
+        div(&cx).children(children).finish()
+    }
+}
@@ -0,0 +1,373 @@
+//! Rendering virtual DOMs to HTML strings.
+//!
+//! This does not touch the physical DOM at all, so it works natively (for
+//! example, to generate the initial page markup on a server) as well as on the
+//! Web.
+
+use crate::{
+    cached_set::CachedSet,
+    node::{ElementNode, Node, NodeKind, RawHtmlNode, Style, TextNode},
+    Render, RenderContext,
+};
+use bumpalo::Bump;
+use fxhash::FxHashMap;
+
+/// Render the given component and serialize the resulting virtual DOM to an
+/// HTML string.
+///
+/// Any `Cached<R>` components in the tree are rendered into a fresh, temporary
+/// cached set and serialized like any other node. Event listeners are ignored.
+/// Portals are serialized as just their placeholder comment, since their
+/// children belong in another container; `Vdom::hydrate` renders them on the
+/// client.
+///
+/// # Example
+///
+/// ```
+/// use dodrio::{render_to_string, Node, Render, RenderContext};
+///
+/// struct Hello;
+///
+/// impl Render for Hello {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         use dodrio::builder::*;
+///         p(&cx)
+///             .attr("class", "greeting")
+///             .children([text("Hello, "), strong(&cx).children([text("world")]).finish()])
+///             .finish()
+///     }
+/// }
+///
+/// assert_eq!(
+///     render_to_string(&Hello),
+///     r#"<p class="greeting">Hello, <strong>world</strong></p>"#
+/// );
+/// ```
+pub fn render_to_string<R>(component: &R) -> String
+where
+    R: ?Sized + Render,
+{
+    let bump = Bump::new();
+    let cached_set = crate::RefCell::new(CachedSet::default());
+    let mut templates = FxHashMap::default();
+
+    let mut cx = RenderContext::new(&bump, &cached_set, &mut templates);
+    let node = component.render(&mut cx);
+
+    let cached_set = cached_set.borrow();
+    let mut html = String::new();
+    write_node(&cached_set, &mut html, &node, None);
+    html
+}
+
+// Elements that never have children and must not have a closing tag.
+//
+// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+fn is_void_element(tag_name: &str) -> bool {
+    matches!(
+        tag_name,
+        "area"
+            | "base"
+            | "br"
+            | "col"
+            | "embed"
+            | "hr"
+            | "img"
+            | "input"
+            | "link"
+            | "meta"
+            | "param"
+            | "source"
+            | "track"
+            | "wbr"
+    )
+}
+
+// Elements whose text contents are not parsed as markup, and therefore must not
+// be escaped either.
+fn is_raw_text_element(tag_name: &str) -> bool {
+    matches!(tag_name, "script" | "style")
+}
+
+// Follow cached nodes to the node that will actually be serialized.
+fn resolve<'a>(cached_set: &'a CachedSet, node: &'a Node<'a>) -> &'a Node<'a> {
+    match node.kind {
+        NodeKind::Cached(ref c) => resolve(cached_set, cached_set.get(c.id).0),
+        _ => node,
+    }
+}
+
+fn write_node(cached_set: &CachedSet, html: &mut String, node: &Node, namespace: Option<&str>) {
+    match resolve(cached_set, node).kind {
+        NodeKind::Text(TextNode { text }) => escape(html, text, false),
+        NodeKind::Element(el) => write_element(cached_set, html, el, namespace),
+        NodeKind::Portal(_) => html.push_str("<!--portal-->"),
+        NodeKind::Empty => html.push_str("<!--none-->"),
+        NodeKind::RawHtml(RawHtmlNode {
+            tag_name,
+            namespace: wrapper_namespace,
+            html: markup,
+        }) => {
+            html.push('<');
+            html.push_str(tag_name);
+            if let Some(wrapper_namespace) = wrapper_namespace {
+                if Some(wrapper_namespace) != namespace {
+                    html.push_str(" xmlns=\"");
+                    escape(html, wrapper_namespace, true);
+                    html.push('"');
+                }
+            }
+            html.push('>');
+            html.push_str(markup);
+            html.push_str("</");
+            html.push_str(tag_name);
+            html.push('>');
+        }
+        NodeKind::Fragment(fragment) => {
+            write_children(cached_set, html, fragment.children, namespace, false)
+        }
+        NodeKind::Cached(_) => unreachable!("resolved cached nodes are never cached nodes"),
+    }
+}
+
+fn write_element(
+    cached_set: &CachedSet,
+    html: &mut String,
+    el: &ElementNode,
+    parent_namespace: Option<&str>,
+) {
+    html.push('<');
+    html.push_str(el.tag_name);
+
+    // Foreign elements (e.g. `<svg>`) need to declare their namespace if they
+    // do not inherit it from their parent.
+    if let Some(namespace) = el.namespace {
+        if el.namespace != parent_namespace && el.attributes.iter().all(|a| a.name != "xmlns") {
+            html.push_str(" xmlns=\"");
+            escape(html, namespace, true);
+            html.push('"');
+        }
+    }
+
+    for attr in el.attributes {
+        html.push(' ');
+        html.push_str(attr.name);
+        html.push_str("=\"");
+        escape(html, attr.value, true);
+        html.push('"');
+    }
+
+    if !el.classes.is_empty() {
+        html.push_str(" class=\"");
+        escape(html, &class_attribute(el.classes), true);
+        html.push('"');
+    }
+
+    if !el.styles.is_empty() {
+        html.push_str(" style=\"");
+        escape(html, &style_attribute(el.styles), true);
+        html.push('"');
+    }
+
+    if el.namespace.is_none() && is_void_element(el.tag_name) {
+        debug_assert!(
+            el.children.is_empty(),
+            "void element `<{}>` cannot have children",
+            el.tag_name
+        );
+        html.push('>');
+        return;
+    }
+
+    if el.namespace.is_some() && el.children.is_empty() {
+        html.push_str("/>");
+        return;
+    }
+
+    html.push('>');
+
+    let raw_text = el.namespace.is_none() && is_raw_text_element(el.tag_name);
+    write_children(cached_set, html, el.children, el.namespace, raw_text);
+
+    html.push_str("</");
+    html.push_str(el.tag_name);
+    html.push('>');
+}
+
+fn write_children(
+    cached_set: &CachedSet,
+    html: &mut String,
+    children: &[Node],
+    namespace: Option<&str>,
+    raw_text: bool,
+) {
+    // Splice in fragments first, so that adjacent text is detected across
+    // fragment boundaries too.
+    let children = cached_set.flatten(children, &mut |_| {});
+    let mut previous_was_text = false;
+    for child in children.iter() {
+        let child = resolve(cached_set, child);
+        match child.kind {
+            NodeKind::Text(TextNode { text }) => {
+                // An HTML parser merges adjacent text into a single text node,
+                // so separate adjacent virtual text nodes with an empty comment
+                // to keep the same number of physical text nodes.
+                if previous_was_text {
+                    html.push_str("<!---->");
+                }
+                previous_was_text = true;
+                if raw_text {
+                    html.push_str(text);
+                } else {
+                    escape(html, text, false);
+                }
+            }
+            _ => {
+                previous_was_text = false;
+                write_node(cached_set, html, child, namespace);
+            }
+        }
+    }
+}
+
+/// Serialize inline style declarations into a `style` attribute's value, the
+/// same way that browsers do.
+pub(crate) fn style_attribute(styles: &[Style]) -> String {
+    let mut style = String::new();
+    for (i, s) in styles.iter().enumerate() {
+        if i > 0 {
+            style.push(' ');
+        }
+        style.push_str(s.property);
+        style.push_str(": ");
+        style.push_str(s.value);
+        style.push(';');
+    }
+    style
+}
+
+/// Serialize class tokens into a `class` attribute's value, skipping
+/// duplicates the same way that `classList.add` does.
+pub(crate) fn class_attribute(classes: &[&str]) -> String {
+    let mut class = String::new();
+    for (i, c) in classes.iter().enumerate() {
+        if classes[..i].contains(c) {
+            continue;
+        }
+        if !class.is_empty() {
+            class.push(' ');
+        }
+        class.push_str(c);
+    }
+    class
+}
+
+pub(crate) fn escape(html: &mut String, s: &str, in_attribute: bool) {
+    for c in s.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' if in_attribute => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_to_string;
+    use crate::{builder::*, Cached, Node, Render, RenderContext};
+
+    struct Page;
+
+    impl Render for Page {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            div(&cx)
+                .attr("id", "page")
+                .attr("title", "\"quoted\" & <tagged>")
+                .on("click", |_, _, _| {})
+                .children([
+                    text("a < b && c > d"),
+                    text("second"),
+                    br(&cx).finish(),
+                    input(&cx).bool_attr("checked", true).finish(),
+                    script(&cx).children([text("if (a < b) {}")]).finish(),
+                    raw_html("<b>raw</b>"),
+                    svg(cx.bump)
+                        .children([
+                            path(cx.bump).attr("d", "M 0 0").finish(),
+                            raw_html_in("g", Some("http://www.w3.org/2000/svg"), "<circle/>"),
+                        ])
+                        .finish(),
+                ])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn elements_attributes_and_text() {
+        assert_eq!(
+            render_to_string(&Page),
+            "<div id=\"page\" title=\"&quot;quoted&quot; &amp; &lt;tagged&gt;\">\
+             a &lt; b &amp;&amp; c &gt; d<!---->second\
+             <br>\
+             <input checked=\"\">\
+             <script>if (a < b) {}</script>\
+             <span><b>raw</b></span>\
+             <svg xmlns=\"http://www.w3.org/2000/svg\"><path d=\"M 0 0\"/><g><circle/></g></svg>\
+             </div>"
+        );
+    }
+
+    #[derive(Default)]
+    struct Greeting {
+        who: &'static str,
+    }
+
+    impl Render for Greeting {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            p(&cx).children([text("Hello, "), text(self.who)]).finish()
+        }
+    }
+
+    struct Greetings;
+
+    impl Render for Greetings {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let cached = Cached::new(Greeting { who: "cache" });
+            ul(&cx)
+                .children([li(&cx).children([cached.render(cx)]).finish()])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn cached_nodes_are_resolved() {
+        assert_eq!(
+            render_to_string(&Greetings),
+            "<ul><li><p>Hello, <!---->cache</p></li></ul>"
+        );
+    }
+
+    struct Fragments;
+
+    impl Render for Fragments {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            fragment(
+                &cx,
+                [
+                    text("one"),
+                    fragment(&cx, [text("two"), br(&cx).finish()]),
+                    fragment(&cx, []),
+                    text("three"),
+                ],
+            )
+        }
+    }
+
+    #[test]
+    fn fragments_are_spliced_into_their_parent() {
+        assert_eq!(render_to_string(&Fragments), "one<!---->two<br>three");
+    }
+}
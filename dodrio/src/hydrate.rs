@@ -0,0 +1,518 @@
+//! Hydrating existing, server-rendered markup.
+//!
+//! Instead of building the physical DOM from scratch, we walk the physical DOM
+//! that is already inside the container alongside the first virtual DOM we
+//! render. Event listeners are attached, and change list instructions are only
+//! emitted where the existing markup does not match the virtual DOM.
+
+use crate::{
+    cached_set::{CacheId, CachedSet},
+    change_list::ChangeListBuilder,
+    diff::{create, create_and_append_children, create_portal_children, fragment_outside_children},
+    events::EventsRegistry,
+    node::{ElementNode, Node, NodeKind, PortalNode, TextNode},
+};
+use fxhash::FxHashSet;
+use std::cmp::Ordering;
+use std::slice;
+
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
+        pub(crate) type PhysicalNode = crate::headless::Node;
+
+        pub(crate) fn first_child(container: &crate::Element) -> Option<PhysicalNode> {
+            container.first_child()
+        }
+
+        fn children(node: &PhysicalNode) -> Vec<PhysicalNode> {
+            node.child_nodes()
+        }
+
+        fn kind(node: &PhysicalNode) -> PhysicalKind {
+            if let Some(tag_name) = node.tag_name() {
+                PhysicalKind::Element {
+                    tag_name,
+                    namespace: node.namespace_uri(),
+                }
+            } else if node.is_comment() {
+                PhysicalKind::Comment(node.text_content())
+            } else {
+                PhysicalKind::Text(node.text_content())
+            }
+        }
+
+        fn attribute_names(node: &PhysicalNode) -> Vec<String> {
+            node.get_attribute_names()
+        }
+
+        fn get_attribute(node: &PhysicalNode, name: &str) -> Option<String> {
+            node.get_attribute(name)
+        }
+    } else {
+        use wasm_bindgen::JsCast;
+
+        pub(crate) type PhysicalNode = web_sys::Node;
+
+        const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+
+        pub(crate) fn first_child(container: &crate::Element) -> Option<PhysicalNode> {
+            container.first_child()
+        }
+
+        fn children(node: &PhysicalNode) -> Vec<PhysicalNode> {
+            let mut children = vec![];
+            let mut child = node.first_child();
+            while let Some(c) = child {
+                child = c.next_sibling();
+                children.push(c);
+            }
+            children
+        }
+
+        fn kind(node: &PhysicalNode) -> PhysicalKind {
+            match node.node_type() {
+                web_sys::Node::ELEMENT_NODE => {
+                    let el: &web_sys::Element = node.unchecked_ref();
+                    PhysicalKind::Element {
+                        tag_name: el.local_name(),
+                        namespace: el
+                            .namespace_uri()
+                            .filter(|namespace| namespace != XHTML_NAMESPACE),
+                    }
+                }
+                web_sys::Node::TEXT_NODE => {
+                    PhysicalKind::Text(node.text_content().unwrap_or_default())
+                }
+                web_sys::Node::COMMENT_NODE => {
+                    PhysicalKind::Comment(node.text_content().unwrap_or_default())
+                }
+                _ => PhysicalKind::Other,
+            }
+        }
+
+        fn attribute_names(node: &PhysicalNode) -> Vec<String> {
+            let el: &web_sys::Element = node.unchecked_ref();
+            let names = el.get_attribute_names();
+            (0..names.length())
+                .filter_map(|i| names.get(i).as_string())
+                .collect()
+        }
+
+        fn get_attribute(node: &PhysicalNode, name: &str) -> Option<String> {
+            let el: &web_sys::Element = node.unchecked_ref();
+            el.get_attribute(name)
+        }
+    }
+}
+
+#[cfg_attr(
+    all(
+        feature = "xxx-unstable-internal-use-only",
+        not(target_arch = "wasm32")
+    ),
+    allow(dead_code)
+)]
+enum PhysicalKind {
+    Element {
+        tag_name: String,
+        // `None` for HTML elements.
+        namespace: Option<String>,
+    },
+    Text(String),
+    Comment(String),
+    Other,
+}
+
+// Hydrate the `physical` node with the virtual `new` node. Emits instructions to
+// attach `new`'s listeners to `physical` and to fix up any differences between
+// them.
+//
+// Upon entry to this function, the physical DOM node must be on the top of the
+// change list stack:
+//
+//     [... node]
+//
+// The change list stack is in the same state when this function exits.
+pub(crate) fn hydrate(
+    cached_set: &CachedSet,
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    physical: &PhysicalNode,
+    new: &Node,
+    cached_roots: &mut FxHashSet<CacheId>,
+) {
+    match new.kind {
+        NodeKind::Cached(ref c) => {
+            cached_roots.insert(c.id);
+            let (new, _template) = cached_set.get(c.id);
+            hydrate(
+                cached_set,
+                change_list,
+                registry,
+                physical,
+                new,
+                cached_roots,
+            );
+        }
+
+        NodeKind::Text(TextNode { text }) => match kind(physical) {
+            PhysicalKind::Text(ref existing) => {
+                if text != existing {
+                    warn!(
+                        "hydrate: text mismatch: expected {:?}, found {:?}",
+                        text, existing
+                    );
+                    change_list.commit_traversal();
+                    change_list.set_text(text);
+                }
+            }
+            _ => replace(cached_set, change_list, registry, new, cached_roots),
+        },
+
+        NodeKind::Element(el) => match kind(physical) {
+            PhysicalKind::Element {
+                ref tag_name,
+                ref namespace,
+            } if tag_name == el.tag_name && namespace.as_ref().map(|n| &n[..]) == el.namespace => {
+                hydrate_listeners(change_list, registry, el);
+                hydrate_attributes(change_list, physical, el);
+                // Server-rendered markup has no properties, and the user may
+                // have interacted with it before we got here, so set them all.
+                // The interpreter skips the ones that already match.
+                for prop in el.properties {
+                    change_list.commit_traversal();
+                    change_list.set_property(prop.name, prop.value);
+                }
+                if let Some(node_ref) = el.node_ref {
+                    change_list.commit_traversal();
+                    change_list.set_node_ref(node_ref);
+                }
+                unsafe {
+                    // Safety relies on the `Vdom` invoking the queued hooks
+                    // before the end of this render.
+                    change_list.lifecycle().mounted(el.on_mount);
+                }
+                hydrate_children(
+                    cached_set,
+                    change_list,
+                    registry,
+                    physical,
+                    el.children,
+                    cached_roots,
+                );
+            }
+            _ => replace(cached_set, change_list, registry, new, cached_roots),
+        },
+
+        // Server-rendered markup only contains a portal's placeholder, so
+        // create its children afresh.
+        NodeKind::Portal(&PortalNode { target, ref child }) => match kind(physical) {
+            PhysicalKind::Comment(_) => {
+                change_list.commit_traversal();
+                // [... placeholder]
+                let children = cached_set.flatten(slice::from_ref(child), &mut |id| {
+                    cached_roots.insert(id);
+                });
+                create_portal_children(
+                    cached_set,
+                    change_list,
+                    registry,
+                    target,
+                    &children,
+                    0,
+                    cached_roots,
+                );
+            }
+            _ => replace(cached_set, change_list, registry, new, cached_roots),
+        },
+
+        // The browser may serialize the server-rendered markup differently
+        // than the string it was parsed from, so there is no reliable way to
+        // compare them. Trust that the server rendered the same markup.
+        NodeKind::RawHtml(ref raw) => match kind(physical) {
+            PhysicalKind::Element {
+                ref tag_name,
+                ref namespace,
+            } if tag_name == raw.tag_name
+                && namespace.as_ref().map(|n| &n[..]) == raw.namespace => {}
+            _ => replace(cached_set, change_list, registry, new, cached_roots),
+        },
+
+        NodeKind::Empty => match kind(physical) {
+            PhysicalKind::Comment(_) => {}
+            _ => replace(cached_set, change_list, registry, new, cached_roots),
+        },
+
+        NodeKind::Fragment(_) => fragment_outside_children(),
+    }
+}
+
+// The physical node is too different from the virtual node to hydrate, so
+// create the virtual node afresh and replace the physical node with it.
+//
+// Upon entry to this function, the physical DOM node must be on the top of the
+// change list stack:
+//
+//     [... node]
+//
+// When this function returns, the new node has replaced it:
+//
+//     [... new_node]
+fn replace(
+    cached_set: &CachedSet,
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    new: &Node,
+    cached_roots: &mut FxHashSet<CacheId>,
+) {
+    warn!("hydrate: node mismatch: expected {:?}", new);
+    change_list.commit_traversal();
+    create(cached_set, change_list, registry, new, cached_roots);
+    change_list.replace_with();
+}
+
+// Attach every listener to the existing physical node.
+//
+// The listeners' node must be on top of the change list stack:
+//
+//     [... node]
+//
+// The change list stack is left unchanged.
+fn hydrate_listeners(
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    el: &ElementNode,
+) {
+    for l in el.listeners {
+        unsafe {
+            // Safety relies on removing `l` from the registry when the virtual
+            // node is diffed away, just like listeners added in `create`.
+            registry.add(l);
+        }
+        change_list.commit_traversal();
+        change_list.new_event_listener(l);
+    }
+}
+
+// Fix up any attributes that differ between the virtual and physical node.
+//
+// The attributes' node must be on top of the change list stack:
+//
+//     [... node]
+//
+// The change list stack is left unchanged.
+fn hydrate_attributes(
+    change_list: &mut ChangeListBuilder,
+    physical: &PhysicalNode,
+    el: &ElementNode,
+) {
+    for attr in el.attributes {
+        let existing = get_attribute(physical, attr.name);
+        if attr.is_volatile() {
+            // The user may have interacted with the server-rendered markup
+            // before we got here, so always re-set volatile attributes.
+            change_list.commit_traversal();
+            change_list.set_attribute(attr.name, attr.value);
+        } else if existing.as_ref().map(|v| &v[..]) != Some(attr.value) {
+            warn!(
+                "hydrate: attribute mismatch on <{}>: expected {}={:?}, found {:?}",
+                el.tag_name, attr.name, attr.value, existing
+            );
+            change_list.commit_traversal();
+            change_list.set_attribute(attr.name, attr.value);
+        }
+    }
+
+    if !el.styles.is_empty() {
+        let style = crate::html::style_attribute(el.styles);
+        let existing = get_attribute(physical, "style");
+        if existing.as_ref() != Some(&style) {
+            warn!(
+                "hydrate: style mismatch on <{}>: expected {:?}, found {:?}",
+                el.tag_name, style, existing
+            );
+            change_list.commit_traversal();
+            change_list.remove_attribute("style");
+            for s in el.styles {
+                change_list.set_style_property(s.property, s.value);
+            }
+        }
+    }
+
+    if !el.classes.is_empty() {
+        let existing = get_attribute(physical, "class").unwrap_or_default();
+        let mut existing: Vec<&str> = existing.split_whitespace().collect();
+        // The order of classes doesn't matter, only whether they are present.
+        let mut expected = el.classes.to_vec();
+        existing.sort_unstable();
+        existing.dedup();
+        expected.sort_unstable();
+        expected.dedup();
+        if existing != expected {
+            warn!(
+                "hydrate: class mismatch on <{}>: expected {:?}, found {:?}",
+                el.tag_name, expected, existing
+            );
+            change_list.commit_traversal();
+            change_list.remove_attribute("class");
+            for class in el.classes {
+                change_list.add_class(class);
+            }
+        }
+    }
+
+    for name in attribute_names(physical) {
+        // The `style` and `class` attributes hold the element's style
+        // declarations and classes.
+        if (name == "style" && !el.styles.is_empty()) || (name == "class" && !el.classes.is_empty())
+        {
+            continue;
+        }
+        if el.attributes.iter().all(|attr| attr.name != name) {
+            warn!(
+                "hydrate: unexpected attribute on <{}>: {:?}",
+                el.tag_name, name
+            );
+            change_list.commit_traversal();
+            change_list.remove_attribute(&name);
+        }
+    }
+}
+
+// Hydrate the physical node's children with the given virtual children.
+//
+// The parent must be on top of the change list stack when this function is
+// entered:
+//
+//     [... parent]
+//
+// The change list stack is in the same state when this function returns.
+pub(crate) fn hydrate_children(
+    cached_set: &CachedSet,
+    change_list: &mut ChangeListBuilder,
+    registry: &mut EventsRegistry,
+    physical_parent: &PhysicalNode,
+    new: &[Node],
+    cached_roots: &mut FxHashSet<CacheId>,
+) {
+    let new = cached_set.flatten(new, &mut |id| {
+        cached_roots.insert(id);
+    });
+    let new = &new[..];
+    let mut physical = children(physical_parent);
+
+    // Remove the empty comments that `render_to_string` uses to separate
+    // adjacent text nodes. Remove from the end first so that we don't mess up
+    // indices.
+    for i in (0..physical.len()).rev() {
+        if let PhysicalKind::Comment(ref data) = kind(&physical[i]) {
+            if !data.is_empty() {
+                continue;
+            }
+            change_list.commit_traversal();
+            change_list.remove_child(i);
+            physical.remove(i);
+        }
+    }
+
+    if new.is_empty() {
+        if !physical.is_empty() {
+            warn!("hydrate: removing {} unexpected children", physical.len());
+            change_list.commit_traversal();
+            change_list.set_text("");
+        }
+        return;
+    }
+
+    if physical.is_empty() {
+        // Note: this is expected for an element with a single empty text
+        // child, since HTML has no way to represent empty text nodes.
+        change_list.commit_traversal();
+        create_and_append_children(cached_set, change_list, registry, new, cached_roots);
+        return;
+    }
+
+    //     [... parent]
+    change_list.go_down();
+    //     [... parent child]
+
+    for (i, (new_child, physical_child)) in new.iter().zip(physical.iter()).enumerate() {
+        // [... parent prev_child]
+        change_list.go_to_sibling(i);
+        // [... parent this_child]
+        hydrate(
+            cached_set,
+            change_list,
+            registry,
+            physical_child,
+            new_child,
+            cached_roots,
+        );
+    }
+
+    match physical.len().cmp(&new.len()) {
+        Ordering::Greater => {
+            warn!(
+                "hydrate: removing {} unexpected children",
+                physical.len() - new.len()
+            );
+            // [... parent prev_child]
+            change_list.go_to_sibling(new.len());
+            // [... parent first_child_to_remove]
+            change_list.commit_traversal();
+            // None of these physical nodes have listeners in the registry.
+            change_list.remove_self_and_next_siblings();
+            // [... parent]
+        }
+        Ordering::Less => {
+            // [... parent last_child]
+            change_list.go_up();
+            // [... parent]
+            change_list.commit_traversal();
+            create_and_append_children(
+                cached_set,
+                change_list,
+                registry,
+                &new[physical.len()..],
+                cached_roots,
+            );
+        }
+        Ordering::Equal => {
+            // [... parent child]
+            change_list.go_up();
+            // [... parent]
+        }
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::{testing::*, Element, Node};
+    use crate::Vdom;
+
+    #[test]
+    fn hydrate_reuses_existing_markup() {
+        // Build the markup that `render_to_string` would have produced for
+        // `list(&[1, 2], false)`, but with a stale attribute and text.
+        let container = Element::create_element("div");
+        let ul = Node::create_element("ul");
+        ul.set_attribute("data-stale", "yes");
+        for text in &["item 1", "item two"] {
+            let li = Node::create_element("li");
+            li.set_attribute("class", "item");
+            li.append_child(&Node::create_text_node(text));
+            ul.append_child(&li);
+        }
+        container.append_child(&ul);
+        let first_li = ul.first_child().unwrap();
+
+        let vdom = Vdom::hydrate(&container, list(&[1, 2], false));
+        assert_eq!(container.inner_html(), items_html(&[1, 2]));
+        assert_eq!(container.first_child().unwrap(), ul);
+        assert_eq!(ul.first_child().unwrap(), first_li);
+        assert_eq!(ul.event_listeners(), vec!["click".to_string()]);
+
+        vdom.immediately_render_and_diff(list(&[1, 2, 3], false));
+        assert_eq!(container.inner_html(), items_html(&[1, 2, 3]));
+    }
+}
@@ -87,21 +87,43 @@ pub mod change_list;
 
 mod cached;
 mod cached_set;
+#[cfg(feature = "debug-checks")]
+mod debug_checks;
 mod diff;
 mod events;
+mod html;
+mod hydrate;
+mod lifecycle;
+mod memo;
 mod node;
+mod node_ref;
+mod portal;
+mod profile;
 mod render;
 mod render_context;
+mod signal;
+mod std_future;
 mod strace;
+mod update;
 mod vdom;
 
 pub mod builder;
 
 // Re-export items at the top level.
-pub use self::cached::Cached;
-pub use self::node::{Attribute, Listener, Node, NodeKey};
+pub use self::cached::{Cached, CachedTemplate};
+pub use self::cached_set::CacheStats;
+pub use self::change_list::disassembler::{Disassembly, Instruction};
+pub use self::html::render_to_string;
+pub use self::memo::Memo;
+pub use self::node::{
+    Attribute, Listener, Node, NodeKey, OwnedPropertyValue, Property, PropertyValue, Style,
+};
+pub use self::node_ref::NodeRef;
+pub use self::profile::RenderProfile;
 pub use self::render::{Render, RootRender};
 pub use self::render_context::RenderContext;
+pub use self::signal::Signal;
+pub use self::update::{ShouldRender, Update};
 pub use self::vdom::{Vdom, VdomWeak};
 
 cfg_if::cfg_if! {
@@ -112,24 +134,35 @@ cfg_if::cfg_if! {
     }
 }
 
-// Polyfill some Web stuff for benchmarking...
+// Polyfill some Web stuff for testing and benchmarking...
 cfg_if::cfg_if! {
     if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
+        pub mod headless;
+
         /// An element node in the physical DOM.
-        pub type Element = ();
+        pub type Element = self::headless::Element;
 
         pub(crate) type EventsTrampoline = ();
+
+        pub(crate) type NodeRefsTrampoline = ();
     } else {
         /// An element node in the physical DOM.
         pub type Element = web_sys::Element;
 
         pub(crate) type EventsTrampoline = wasm_bindgen::closure::Closure<Fn(web_sys::Event, u32, u32)>;
+
+        pub(crate) type NodeRefsTrampoline =
+            wasm_bindgen::closure::Closure<dyn Fn(u32, Option<web_sys::Element>)>;
     }
 }
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "xxx-unstable-internal-use-only")] {
         pub use self::cached_set::{CachedSet};
-        pub use self::node::{ElementNode, NodeKind, TextNode};
+        pub use self::node::{
+            ElementNode, FragmentNode, LifecycleHook, NodeKind, PortalNode, RawHtmlNode, TextNode,
+        };
+        pub use self::node_ref::NodeRefId;
+        pub use self::portal::PortalTarget;
     }
 }
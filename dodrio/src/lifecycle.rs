@@ -0,0 +1,232 @@
+//! Lifecycle hooks: callbacks that are invoked when elements are mounted into,
+//! or unmounted from, the physical DOM.
+//!
+//! Diffing queues up hooks as it creates and removes elements, and the `Vdom`
+//! invokes them once it is done diffing: unmount hooks before the change list
+//! is applied, while their elements are still in the physical DOM, and mount
+//! hooks after it is applied.
+
+use crate::{
+    cached_set::CachedSet,
+    node::{ElementNode, LifecycleCallback, LifecycleHook, Node, NodeKind},
+    RootRender, VdomWeak,
+};
+use std::mem;
+
+/// Which hooks should be queued for the nodes that are currently being diffed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LifecycleMode {
+    /// Queue hooks for nodes that are created and removed.
+    Normal,
+
+    /// We are creating a cached node's template. The template's nodes are
+    /// only prototypes that will be diffed into the real nodes, so don't queue
+    /// any of their hooks.
+    CreatingTemplate,
+
+    /// We are diffing a template's nodes into the real nodes. Every real node
+    /// is being mounted, and the template nodes that are removed were never
+    /// mounted in the first place.
+    MountingTemplate,
+}
+
+/// The lifecycle hooks queued by a single render's diff.
+#[derive(Debug)]
+pub(crate) struct LifecycleHooks {
+    mode: LifecycleMode,
+    mounted: Vec<LifecycleHook<'static>>,
+    unmounting: Vec<LifecycleHook<'static>>,
+}
+
+impl Default for LifecycleHooks {
+    fn default() -> LifecycleHooks {
+        LifecycleHooks {
+            mode: LifecycleMode::Normal,
+            mounted: vec![],
+            unmounting: vec![],
+        }
+    }
+}
+
+impl LifecycleHooks {
+    pub(crate) fn push_mode(&mut self, mode: LifecycleMode) -> LifecycleMode {
+        let old = self.mode;
+        // Nothing inside a template's prototype nodes is ever mounted.
+        if old != LifecycleMode::CreatingTemplate {
+            self.mode = mode;
+        }
+        old
+    }
+
+    pub(crate) fn pop_mode(&mut self, previous: LifecycleMode) {
+        self.mode = previous;
+    }
+
+    #[inline]
+    pub(crate) fn mounting_template(&self) -> bool {
+        self.mode == LifecycleMode::MountingTemplate
+    }
+
+    /// Queue a newly created element's mount hook, if it has one.
+    ///
+    /// # Unsafety
+    ///
+    /// The hook's lifetime is extended to `'static`, and it is the caller's
+    /// responsibility to invoke the queued hooks before the element's bump
+    /// arena is reset.
+    pub(crate) unsafe fn mounted(&mut self, on_mount: Option<LifecycleHook>) {
+        if self.mode == LifecycleMode::CreatingTemplate {
+            return;
+        }
+        if let Some(hook) = on_mount {
+            self.mounted.push(extend_hook_lifetime(hook));
+        }
+    }
+
+    /// Queue the mount hooks of every element in the given subtree.
+    ///
+    /// # Unsafety
+    ///
+    /// Same as `mounted`.
+    pub(crate) unsafe fn mounted_subtree(&mut self, cached_set: &CachedSet, node: &Node) {
+        for_each_element(cached_set, node, &mut |el| self.mounted(el.on_mount));
+    }
+
+    /// Queue the unmount hooks of every element in the given subtree.
+    ///
+    /// # Unsafety
+    ///
+    /// Same as `mounted`.
+    pub(crate) unsafe fn unmounting_subtree(&mut self, cached_set: &CachedSet, node: &Node) {
+        if self.mode != LifecycleMode::Normal {
+            return;
+        }
+        let unmounting = &mut self.unmounting;
+        for_each_element(cached_set, node, &mut |el| {
+            if let Some(hook) = el.on_unmount {
+                unmounting.push(extend_hook_lifetime(hook));
+            }
+        });
+    }
+
+    /// Invoke and dequeue all of the queued unmount hooks.
+    pub(crate) fn run_unmounting(&mut self, component: &mut dyn RootRender, vdom: &VdomWeak) {
+        run(mem::take(&mut self.unmounting), component, vdom);
+    }
+
+    /// Invoke and dequeue all of the queued mount hooks.
+    pub(crate) fn run_mounted(&mut self, component: &mut dyn RootRender, vdom: &VdomWeak) {
+        run(mem::take(&mut self.mounted), component, vdom);
+    }
+}
+
+unsafe fn extend_hook_lifetime(hook: LifecycleHook) -> LifecycleHook<'static> {
+    LifecycleHook {
+        callback: mem::transmute::<LifecycleCallback, LifecycleCallback<'static>>(hook.callback),
+    }
+}
+
+fn run(hooks: Vec<LifecycleHook>, component: &mut dyn RootRender, vdom: &VdomWeak) {
+    for hook in hooks {
+        (hook.callback)(component, vdom.clone());
+    }
+}
+
+// Call `f` on every element in the given subtree, including the contents of
+// cached nodes and portals.
+fn for_each_element(cached_set: &CachedSet, node: &Node, f: &mut dyn FnMut(&ElementNode)) {
+    match node.kind {
+        NodeKind::Text(_) | NodeKind::RawHtml(_) | NodeKind::Empty => {}
+        NodeKind::Element(el) => {
+            f(el);
+            for child in el.children {
+                for_each_element(cached_set, child, f);
+            }
+        }
+        NodeKind::Cached(ref c) => {
+            let (node, _) = cached_set.get(c.id);
+            for_each_element(cached_set, node, f);
+        }
+        NodeKind::Portal(portal) => for_each_element(cached_set, &portal.child, f),
+        NodeKind::Fragment(fragment) => {
+            for child in fragment.children {
+                for_each_element(cached_set, child, f);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Cached, Node, Render, RenderContext, RootRender};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Default)]
+    struct Clock {
+        name: &'static str,
+    }
+
+    impl Render for Clock {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let name = self.name;
+            span(&cx)
+                .on_mount(move |root, _| Clocks::log(root, "mount", name))
+                .on_unmount(move |root, _| Clocks::log(root, "unmount", name))
+                .finish()
+        }
+    }
+
+    struct Clocks {
+        names: Vec<&'static str>,
+        cached: bool,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Clocks {
+        fn log(root: &mut dyn RootRender, what: &str, name: &str) {
+            let clocks = root.unwrap_mut::<Clocks>();
+            clocks.log.borrow_mut().push(format!("{} {}", what, name));
+        }
+    }
+
+    impl Render for Clocks {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+            for &name in &self.names {
+                let clock = Clock { name };
+                children.push(if self.cached {
+                    Cached::new(clock).render(cx)
+                } else {
+                    clock.render(cx)
+                });
+            }
+            div(&cx).children(children).finish()
+        }
+    }
+
+    #[test]
+    fn lifecycle_hooks() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let clocks = |names: &[&'static str], cached| Clocks {
+            names: names.to_vec(),
+            cached,
+            log: log.clone(),
+        };
+        let take_log = || log.borrow_mut().drain(..).collect::<Vec<_>>();
+
+        let (_container, vdom) = mount(clocks(&["a", "b"], false));
+        assert_eq!(take_log(), ["mount a", "mount b"]);
+
+        vdom.immediately_render_and_diff(clocks(&["a"], false));
+        assert_eq!(take_log(), ["unmount b"]);
+
+        // Cached nodes are created from a template, whose own hooks are never
+        // invoked. Unmount hooks are invoked before mount hooks.
+        vdom.immediately_render_and_diff(clocks(&["a", "c"], true));
+        assert_eq!(take_log(), ["unmount a", "mount a", "mount c"]);
+
+        drop(vdom);
+        assert_eq!(take_log(), ["unmount a", "unmount c"]);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{Cached, Node, Render, RenderContext};
+use std::cell::RefCell;
+
+/// A renderable that is built from a props value, and whose cached rendering
+/// is reused for as long as its props are unchanged.
+///
+/// A `Memo<P, R>` renders an `R` that is constructed from its current props
+/// with `R::from`. The rendering is cached just like a `Cached<R>`'s, but
+/// instead of calling `Cached::invalidate` yourself, you give the `Memo` the
+/// latest props, and it re-renders (and re-diffs) only when they compare
+/// unequal to the props that produced the cached rendering.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, bumpalo, Memo, Node, Render, RenderContext};
+///
+/// #[derive(Clone, Default, PartialEq)]
+/// struct HeaderProps {
+///     title: String,
+///     counter: i32,
+/// }
+///
+/// #[derive(Default)]
+/// struct Header {
+///     props: HeaderProps,
+/// }
+///
+/// impl From<HeaderProps> for Header {
+///     fn from(props: HeaderProps) -> Header {
+///         Header { props }
+///     }
+/// }
+///
+/// impl Render for Header {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let title = bumpalo::format!(in cx.bump, "{} {}", self.props.title, self.props.counter);
+///         h1(&cx).children([text(title.into_bump_str())]).finish()
+///     }
+/// }
+///
+/// struct App {
+///     title: String,
+///     counter1: i32,
+///     counter2: i32,
+///     header: Memo<HeaderProps, Header>,
+/// }
+///
+/// impl Render for App {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         // The header is only re-rendered when `title` or `counter1` change,
+///         // not when `counter2` does.
+///         let props = HeaderProps {
+///             title: self.title.clone(),
+///             counter: self.counter1,
+///         };
+///         let counter2 = bumpalo::format!(in cx.bump, "{}", self.counter2);
+///         div(&cx)
+///             .children([
+///                 self.header.render_with(cx, props),
+///                 p(&cx).children([text(counter2.into_bump_str())]).finish(),
+///             ])
+///             .finish()
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Memo<P, R>
+where
+    R: Default,
+{
+    props: RefCell<P>,
+    cached: RefCell<Cached<R>>,
+}
+
+impl<P, R> Memo<P, R>
+where
+    P: PartialEq + Clone,
+    R: 'static + Default + From<P>,
+{
+    /// Construct a new `Memo<P, R>` with the given initial props.
+    pub fn new(props: P) -> Memo<P, R> {
+        let cached = Cached::new(R::from(props.clone()));
+        Memo {
+            props: RefCell::new(props),
+            cached: RefCell::new(cached),
+        }
+    }
+
+    /// Get a copy of the current props.
+    pub fn props(&self) -> P {
+        self.props.borrow().clone()
+    }
+
+    /// Update the props.
+    ///
+    /// If the new props compare unequal to the current props, the inner `R`
+    /// is rebuilt from them and the cached rendering is invalidated. Returns
+    /// whether that happened.
+    pub fn set_props(&self, props: P) -> bool {
+        if *self.props.borrow() == props {
+            return false;
+        }
+        let mut cached = self.cached.borrow_mut();
+        **cached = R::from(props.clone());
+        Cached::invalidate(&cached);
+        *self.props.borrow_mut() = props;
+        true
+    }
+
+    /// Update the props with `set_props`, and then render.
+    pub fn render_with<'a>(&self, cx: &mut RenderContext<'a>, props: P) -> Node<'a>
+    where
+        R: 'static + Render,
+    {
+        self.set_props(props);
+        self.render(cx)
+    }
+}
+
+impl<P, R> Default for Memo<P, R>
+where
+    P: Default + PartialEq + Clone,
+    R: 'static + Default + From<P>,
+{
+    fn default() -> Memo<P, R> {
+        Memo::new(P::default())
+    }
+}
+
+impl<P, R> Render for Memo<P, R>
+where
+    R: 'static + Default + Render,
+{
+    fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+        self.cached.borrow().render(cx)
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use super::Memo;
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Node, Render, RenderContext};
+    use std::{cell::Cell, rc::Rc};
+
+    #[derive(Default)]
+    struct Tens(u32);
+
+    impl From<u32> for Tens {
+        fn from(tens: u32) -> Tens {
+            Tens(tens)
+        }
+    }
+
+    impl Render for Tens {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let tens = bumpalo::format!(in cx.bump, "{}0s", self.0);
+            p(&cx).children([text(tens.into_bump_str())]).finish()
+        }
+    }
+
+    struct Counts {
+        count: Rc<Cell<u32>>,
+        tens: Memo<u32, Tens>,
+    }
+
+    impl Render for Counts {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            self.tens.render_with(cx, self.count.get() / 10)
+        }
+    }
+
+    #[test]
+    fn memos_rerender_when_their_props_change() {
+        let count = Rc::new(Cell::new(0));
+        let (container, vdom) = mount(Counts {
+            count: count.clone(),
+            tens: Memo::default(),
+        });
+        assert_eq!(container.inner_html(), "<p>00s</p>");
+
+        count.set(9);
+        vdom.weak().render_sync().unwrap();
+        assert_eq!(cache_hits_and_misses(&vdom), (1, 0));
+
+        count.set(10);
+        vdom.weak().render_sync().unwrap();
+        assert_eq!(container.inner_html(), "<p>10s</p>");
+        assert_eq!(cache_hits_and_misses(&vdom), (0, 1));
+    }
+}
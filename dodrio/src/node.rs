@@ -1,4 +1,4 @@
-use crate::{cached_set::CacheId, RootRender, VdomWeak};
+use crate::{cached_set::CacheId, node_ref::NodeRefId, portal::PortalTarget, RootRender, VdomWeak};
 use bumpalo::Bump;
 use std::fmt;
 use std::iter;
@@ -29,6 +29,18 @@ pub_unstable_internal! {
         /// A node in the vdom's `CachedSet`. This allows us to avoid
         /// re-rendering and re-diffing subtrees.
         Cached(CachedNode),
+
+        /// A node whose child is rendered into a different container.
+        Portal(&'a PortalNode<'a>),
+
+        /// A list of nodes that are spliced into their parent's children.
+        Fragment(&'a FragmentNode<'a>),
+
+        /// Trusted markup that is rendered inside a wrapper element.
+        RawHtml(RawHtmlNode<'a>),
+
+        /// Nothing, whose place among its siblings is held by a comment.
+        Empty,
     }
 }
 
@@ -50,8 +62,14 @@ pub_unstable_internal! {
         pub tag_name: &'a str,
         pub listeners: &'a [Listener<'a>],
         pub attributes: &'a [Attribute<'a>],
+        pub properties: &'a [Property<'a>],
+        pub styles: &'a [Style<'a>],
+        pub classes: &'a [&'a str],
         pub children: &'a [Node<'a>],
         pub namespace: Option<&'a str>,
+        pub node_ref: Option<NodeRefId>,
+        pub on_mount: Option<LifecycleHook<'a>>,
+        pub on_unmount: Option<LifecycleHook<'a>>,
     }
 }
 
@@ -68,6 +86,45 @@ pub_unstable_internal! {
     }
 }
 
+pub_unstable_internal! {
+    /// A portal renders its child into a target container elsewhere in the
+    /// physical DOM, rather than into its parent. If the child is a fragment,
+    /// each of the fragment's children is rendered into the target. Its place
+    /// in the parent is held by a `<!--portal-->` comment.
+    #[derive(Debug, Clone)]
+    pub(crate) struct PortalNode<'a> {
+        pub target: PortalTarget,
+        pub child: Node<'a>,
+    }
+}
+
+pub_unstable_internal! {
+    /// A fragment has no physical DOM node of its own. Its children are
+    /// rendered in its place, as siblings of its own siblings.
+    #[derive(Debug, Clone)]
+    pub(crate) struct FragmentNode<'a> {
+        pub children: &'a [Node<'a>],
+    }
+}
+
+pub_unstable_internal! {
+    /// Raw HTML nodes are a string of markup that is set as the `innerHTML` of
+    /// a wrapper element. The markup is not escaped or checked in any way.
+    #[derive(Debug, Clone)]
+    pub(crate) struct RawHtmlNode<'a> {
+        pub tag_name: &'a str,
+        pub namespace: Option<&'a str>,
+        pub html: &'a str,
+    }
+}
+
+impl RawHtmlNode<'_> {
+    /// The tag name of the element that raw HTML is wrapped in by default.
+    /// Since `<span>`s are allowed almost everywhere that HTML is, including
+    /// inside `<p>`s and `<button>`s, the markup can be placed anywhere.
+    pub(crate) const DEFAULT_TAG_NAME: &'static str = "span";
+}
+
 /// The key for keyed children.
 ///
 /// Keys must be unique among siblings.
@@ -126,6 +183,23 @@ pub struct Listener<'a> {
     pub(crate) callback: ListenerCallback<'a>,
 }
 
+/// A lifecycle hook callback function.
+///
+/// It takes two parameters:
+///
+/// 1. The virtual DOM's root rendering component.
+/// 2. A capability to schedule virtual DOM re-rendering.
+pub(crate) type LifecycleCallback<'a> = &'a (dyn Fn(&mut dyn RootRender, VdomWeak) + 'static);
+
+pub_unstable_internal! {
+    /// A callback that is invoked when an element is mounted into, or about to
+    /// be unmounted from, the physical DOM.
+    #[derive(Clone, Copy)]
+    pub(crate) struct LifecycleHook<'a> {
+        pub(crate) callback: LifecycleCallback<'a>,
+    }
+}
+
 /// An attribute on a DOM node, such as `id="my-thing"` or
 /// `href="https://example.com"`.
 #[derive(Clone, Debug)]
@@ -134,6 +208,122 @@ pub struct Attribute<'a> {
     pub(crate) value: &'a str,
 }
 
+/// An inline style declaration on a DOM node, such as `color: red`.
+#[derive(Clone, Debug)]
+pub struct Style<'a> {
+    pub(crate) property: &'a str,
+    pub(crate) value: &'a str,
+}
+
+/// A property on a DOM node, such as an `<input>`'s `value` or `checked`.
+///
+/// Unlike attributes, properties are live DOM state that the user can change,
+/// so they are set on the physical DOM node whenever they differ from it.
+#[derive(Clone, Debug)]
+pub struct Property<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) value: PropertyValue<'a>,
+}
+
+/// The value of a DOM property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PropertyValue<'a> {
+    /// A string value, such as an `<input>`'s `value`.
+    String(&'a str),
+
+    /// A boolean value, such as a checkbox's `checked`.
+    Bool(bool),
+
+    /// A number value, such as an `<input type="range">`'s `valueAsNumber`.
+    Number(f64),
+}
+
+impl<'a> From<&'a str> for PropertyValue<'a> {
+    #[inline]
+    fn from(s: &'a str) -> PropertyValue<'a> {
+        PropertyValue::String(s)
+    }
+}
+
+impl From<bool> for PropertyValue<'_> {
+    #[inline]
+    fn from(b: bool) -> Self {
+        PropertyValue::Bool(b)
+    }
+}
+
+impl From<f64> for PropertyValue<'_> {
+    #[inline]
+    fn from(n: f64) -> Self {
+        PropertyValue::Number(n)
+    }
+}
+
+impl From<i32> for PropertyValue<'_> {
+    #[inline]
+    fn from(n: i32) -> Self {
+        PropertyValue::Number(n.into())
+    }
+}
+
+impl From<u32> for PropertyValue<'_> {
+    #[inline]
+    fn from(n: u32) -> Self {
+        PropertyValue::Number(n.into())
+    }
+}
+
+impl PropertyValue<'_> {
+    /// Copy this value out of the bump arena it was rendered into.
+    pub fn into_owned(self) -> OwnedPropertyValue {
+        match self {
+            PropertyValue::String(s) => OwnedPropertyValue::String(s.to_string()),
+            PropertyValue::Bool(b) => OwnedPropertyValue::Bool(b),
+            PropertyValue::Number(n) => OwnedPropertyValue::Number(n),
+        }
+    }
+}
+
+/// An owned `PropertyValue`, such as one decoded from a change list.
+///
+/// Numbers are compared by their bits, so unlike `f64`s, these implement `Eq`.
+#[derive(Clone, Debug)]
+pub enum OwnedPropertyValue {
+    /// A string value.
+    String(String),
+
+    /// A boolean value.
+    Bool(bool),
+
+    /// A number value.
+    Number(f64),
+}
+
+impl PartialEq for OwnedPropertyValue {
+    fn eq(&self, other: &OwnedPropertyValue) -> bool {
+        match (self, other) {
+            (OwnedPropertyValue::String(a), OwnedPropertyValue::String(b)) => a == b,
+            (OwnedPropertyValue::Bool(a), OwnedPropertyValue::Bool(b)) => a == b,
+            (OwnedPropertyValue::Number(a), OwnedPropertyValue::Number(b)) => {
+                a.to_bits() == b.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for OwnedPropertyValue {}
+
+impl fmt::Display for OwnedPropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OwnedPropertyValue::String(s) => write!(f, "{:?}", s),
+            OwnedPropertyValue::Bool(b) => write!(f, "{}", b),
+            OwnedPropertyValue::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
 impl<'a> From<CachedNode> for Node<'a> {
     #[inline]
     fn from(c: CachedNode) -> Self {
@@ -155,6 +345,13 @@ impl fmt::Debug for Listener<'_> {
     }
 }
 
+impl fmt::Debug for LifecycleHook<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let callback = self.callback as *const _ as *const u8;
+        f.debug_tuple("LifecycleHook").field(&callback).finish()
+    }
+}
+
 impl<'a> Attribute<'a> {
     /// Get this attribute's name, such as `"id"` in `<div id="my-thing" />`.
     #[inline]
@@ -174,10 +371,36 @@ impl<'a> Attribute<'a> {
     /// DOM node, even if the old and new virtual DOM nodes have the same value.
     #[inline]
     pub(crate) fn is_volatile(&self) -> bool {
-        match self.name {
-            "value" | "checked" | "selected" => true,
-            _ => false,
-        }
+        matches!(self.name, "value" | "checked" | "selected")
+    }
+}
+
+impl<'a> Style<'a> {
+    /// Get this declaration's CSS property name, such as `"color"` in
+    /// `color: red`.
+    #[inline]
+    pub fn property(&self) -> &'a str {
+        self.property
+    }
+
+    /// Get this declaration's value, such as `"red"` in `color: red`.
+    #[inline]
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+}
+
+impl<'a> Property<'a> {
+    /// Get this property's name, such as `"value"`.
+    #[inline]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Get this property's value.
+    #[inline]
+    pub fn value(&self) -> PropertyValue<'a> {
+        self.value
     }
 }
 
@@ -198,15 +421,29 @@ impl<'a> Node<'a> {
         children: &'a [Node<'a>],
         namespace: Option<&'a str>,
     ) -> Node<'a> {
-        let element = bump.alloc_with(|| ElementNode {
-            key,
-            tag_name,
-            listeners,
-            attributes,
-            children,
-            namespace,
-        });
+        Node::from_element(
+            bump,
+            ElementNode {
+                key,
+                tag_name,
+                listeners,
+                attributes,
+                properties: &[],
+                styles: &[],
+                classes: &[],
+                children,
+                namespace,
+                node_ref: None,
+                on_mount: None,
+                on_unmount: None,
+            },
+        )
+    }
 
+    /// Construct a new element node from its parts.
+    #[inline]
+    pub(crate) fn from_element(bump: &'a Bump, element: ElementNode<'a>) -> Node<'a> {
+        let element = bump.alloc_with(|| element);
         Node {
             kind: NodeKind::Element(element),
         }
@@ -220,12 +457,60 @@ impl<'a> Node<'a> {
         }
     }
 
+    /// Construct a new portal node that renders `child` into `target`.
+    #[inline]
+    pub(crate) fn portal(bump: &'a Bump, target: &crate::Element, child: Node<'a>) -> Node<'a> {
+        let target = PortalTarget::for_element(target);
+        let portal = bump.alloc_with(|| PortalNode { target, child });
+        Node {
+            kind: NodeKind::Portal(portal),
+        }
+    }
+
+    /// Construct a new fragment node with the given children.
+    #[inline]
+    pub(crate) fn fragment(bump: &'a Bump, children: &'a [Node<'a>]) -> Node<'a> {
+        let fragment = bump.alloc_with(|| FragmentNode { children });
+        Node {
+            kind: NodeKind::Fragment(fragment),
+        }
+    }
+
+    /// Construct a new raw HTML node with the given wrapper element and
+    /// markup.
+    #[inline]
+    pub(crate) fn raw_html(
+        tag_name: &'a str,
+        namespace: Option<&'a str>,
+        html: &'a str,
+    ) -> Node<'a> {
+        Node {
+            kind: NodeKind::RawHtml(RawHtmlNode {
+                tag_name,
+                namespace,
+                html,
+            }),
+        }
+    }
+
+    /// Construct a new empty node.
+    #[inline]
+    pub(crate) fn empty() -> Node<'a> {
+        Node {
+            kind: NodeKind::Empty,
+        }
+    }
+
     #[inline]
     pub(crate) fn key(&self) -> NodeKey {
         match &self.kind {
-            NodeKind::Text(_) => NodeKey::NONE,
+            NodeKind::Text(_) | NodeKind::RawHtml(_) | NodeKind::Empty => NodeKey::NONE,
             NodeKind::Element(e) => e.key,
             NodeKind::Cached(c) => c.key,
+            NodeKind::Portal(p) => p.child.key(),
+            // Fragments are spliced into their parent's children, where their
+            // own children's keys are what matter.
+            NodeKind::Fragment(_) => NodeKey::NONE,
         }
     }
 }
@@ -246,6 +531,15 @@ impl<'a> IntoIterator for Node<'a> {
     }
 }
 
+/// `None` becomes an empty node, so that optional children can be rendered
+/// with `.into()` in a fixed-size children array.
+impl<'a> From<Option<Node<'a>>> for Node<'a> {
+    #[inline]
+    fn from(node: Option<Node<'a>>) -> Node<'a> {
+        node.unwrap_or_else(Node::empty)
+    }
+}
+
 union CallbackFatPtr<'a> {
     callback: ListenerCallback<'a>,
     parts: (u32, u32),
@@ -0,0 +1,239 @@
+//! Node refs: handles to the physical DOM elements that are rendered for
+//! virtual elements.
+//!
+//! Just like portal targets, virtual DOM nodes cannot own a `NodeRef` because
+//! bump arenas never run destructors. Instead, every live `NodeRef` is
+//! registered here under a unique id, and elements refer to it by that id. The
+//! change list interpreter reports which physical element (if any) each id
+//! refers to.
+
+use fxhash::FxHashMap;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+pub_unstable_internal! {
+    /// An identifier for a `NodeRef`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub(crate) struct NodeRefId(u32);
+}
+
+impl From<NodeRefId> for u32 {
+    #[inline]
+    fn from(id: NodeRefId) -> u32 {
+        id.0
+    }
+}
+
+thread_local! {
+    static NEXT_ID: Cell<u32> = const { Cell::new(0) };
+
+    // The live node refs, by id.
+    static REGISTRY: RefCell<FxHashMap<u32, Weak<NodeRefInner>>> =
+        RefCell::new(FxHashMap::default());
+}
+
+/// A handle to the physical DOM element that is rendered for a virtual
+/// element.
+///
+/// Attach a `NodeRef` to an element with `ElementBuilder::node_ref`. Once the
+/// element has been created in the physical DOM, `get` returns it. When the
+/// element is removed from the physical DOM, the `NodeRef` is cleared again.
+///
+/// `NodeRef` is a cheap, reference-counted handle: clones refer to the same
+/// element.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, bumpalo, Node, NodeRef, Render, RenderContext};
+///
+/// #[derive(Default)]
+/// struct SearchBox {
+///     input: NodeRef,
+/// }
+///
+/// impl SearchBox {
+///     fn input(&self) -> Option<dodrio::Element> {
+///         // `None` until the component has been rendered into the DOM.
+///         self.input.get()
+///     }
+/// }
+///
+/// impl Render for SearchBox {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         input(&cx)
+///             .attr("type", "search")
+///             .node_ref(&self.input)
+///             .finish()
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct NodeRef {
+    inner: Rc<NodeRefInner>,
+}
+
+struct NodeRefInner {
+    id: u32,
+    element: RefCell<Option<crate::Element>>,
+}
+
+impl Drop for NodeRefInner {
+    fn drop(&mut self) {
+        // Ignore the error if the registry has already been destroyed, which
+        // happens when `NodeRef`s are dropped during thread teardown.
+        let _ = REGISTRY.try_with(|registry| registry.borrow_mut().remove(&self.id));
+    }
+}
+
+impl fmt::Debug for NodeRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NodeRef")
+            .field("id", &self.inner.id)
+            .field("element", &self.inner.element.borrow())
+            .finish()
+    }
+}
+
+impl Default for NodeRef {
+    fn default() -> NodeRef {
+        NodeRef::new()
+    }
+}
+
+impl NodeRef {
+    /// Construct a new, empty `NodeRef`.
+    pub fn new() -> NodeRef {
+        let id = NEXT_ID.with(|next| {
+            let id = next.get();
+            next.set(id.checked_add(1).expect("too many `NodeRef`s"));
+            id
+        });
+        let inner = Rc::new(NodeRefInner {
+            id,
+            element: RefCell::new(None),
+        });
+        REGISTRY.with(|registry| {
+            registry.borrow_mut().insert(id, Rc::downgrade(&inner));
+        });
+        NodeRef { inner }
+    }
+
+    /// Get the physical DOM element that this ref is attached to, if that
+    /// element is currently rendered.
+    pub fn get(&self) -> Option<crate::Element> {
+        self.inner.element.borrow().clone()
+    }
+
+    #[inline]
+    pub(crate) fn id(&self) -> NodeRefId {
+        NodeRefId(self.inner.id)
+    }
+}
+
+/// Set or clear the element for the node ref with the given id.
+///
+/// This is called by the change list interpreter. Ids whose `NodeRef` has
+/// already been dropped are ignored.
+pub(crate) fn set_element(id: u32, element: Option<crate::Element>) {
+    let node_ref = REGISTRY.with(|registry| registry.borrow().get(&id).and_then(Weak::upgrade));
+    if let Some(node_ref) = node_ref {
+        *node_ref.element.borrow_mut() = element;
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(not(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32"))))] {
+        use wasm_bindgen::closure::Closure;
+
+        thread_local! {
+            // The JS function that the change list interpreter calls to set
+            // and clear node refs. It is shared by every `Vdom` on this thread.
+            static TRAMPOLINE: crate::NodeRefsTrampoline = Closure::wrap(Box::new(set_element)
+                as Box<dyn Fn(u32, Option<web_sys::Element>)>);
+        }
+
+        /// Invoke `f` with the node refs trampoline.
+        pub(crate) fn with_trampoline<F>(f: F)
+        where
+            F: FnOnce(&crate::NodeRefsTrampoline),
+        {
+            TRAMPOLINE.with(f);
+        }
+    } else {
+        /// The headless interpreter sets node refs directly, so there is no
+        /// trampoline.
+        pub(crate) fn with_trampoline<F>(f: F)
+        where
+            F: FnOnce(&crate::NodeRefsTrampoline),
+        {
+            f(&());
+        }
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use super::NodeRef;
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Node, Render, RenderContext};
+
+    struct Focusable {
+        node_ref: NodeRef,
+        // Which of the `<input>`s the node ref is attached to, if any.
+        attach_to: Option<usize>,
+        count: usize,
+    }
+
+    impl Render for Focusable {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+            for i in 0..self.count {
+                let el = input(&cx);
+                let el = if self.attach_to == Some(i) {
+                    el.node_ref(&self.node_ref)
+                } else {
+                    el
+                };
+                children.push(el.finish());
+            }
+            div(&cx).children(children).finish()
+        }
+    }
+
+    #[test]
+    fn node_refs() {
+        let node_ref = NodeRef::new();
+        let focusable = |attach_to, count| Focusable {
+            node_ref: node_ref.clone(),
+            attach_to,
+            count,
+        };
+
+        let (container, vdom) = mount(focusable(Some(1), 2));
+        let div = container.first_child().unwrap();
+        let inputs = div.child_nodes();
+        assert_eq!(node_ref.get(), Some(inputs[1].clone()));
+
+        // Moving the ref to a different element.
+        vdom.immediately_render_and_diff(focusable(Some(0), 2));
+        assert_eq!(node_ref.get(), Some(inputs[0].clone()));
+
+        // Detaching the ref from an element that is still rendered.
+        vdom.immediately_render_and_diff(focusable(None, 2));
+        assert_eq!(node_ref.get(), None);
+
+        // Removing the element that the ref is attached to.
+        vdom.immediately_render_and_diff(focusable(Some(1), 2));
+        assert_eq!(node_ref.get(), Some(inputs[1].clone()));
+        vdom.immediately_render_and_diff(focusable(Some(1), 1));
+        assert_eq!(node_ref.get(), None);
+
+        // Unmounting the vdom.
+        vdom.immediately_render_and_diff(focusable(Some(0), 1));
+        assert_eq!(node_ref.get(), Some(inputs[0].clone()));
+        drop(vdom);
+        assert_eq!(node_ref.get(), None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Portal targets: the containers that portals render their children into.
+//!
+//! Virtual DOM nodes are allocated in bump arenas that never run destructors,
+//! so a `PortalNode` cannot own its target element. Instead, every target
+//! element is registered here once, and nodes refer to it by a plain id.
+
+use std::cell::RefCell;
+
+pub_unstable_internal! {
+    /// An identifier for a container element that portals render into.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub(crate) struct PortalTarget(u32);
+}
+
+thread_local! {
+    // The registered target elements, indexed by their `PortalTarget` id.
+    //
+    // Targets are never unregistered. In practice there are only a handful of
+    // them (e.g. `document.body`) and they live as long as the page does.
+    static TARGETS: RefCell<Vec<crate::Element>> = const { RefCell::new(Vec::new()) };
+}
+
+impl PortalTarget {
+    /// Get the id for the given target element, registering it if this is the
+    /// first time it has been used as a portal target.
+    pub(crate) fn for_element(element: &crate::Element) -> PortalTarget {
+        TARGETS.with(|targets| {
+            let mut targets = targets.borrow_mut();
+            let index = match targets.iter().position(|t| t == element) {
+                Some(index) => index,
+                None => {
+                    targets.push(element.clone());
+                    targets.len() - 1
+                }
+            };
+            PortalTarget(index as u32)
+        })
+    }
+
+    /// Get this target's element.
+    pub(crate) fn element(self) -> crate::Element {
+        TARGETS.with(|targets| targets.borrow()[self.0 as usize].clone())
+    }
+}
+
+impl From<PortalTarget> for u32 {
+    #[inline]
+    fn from(target: PortalTarget) -> u32 {
+        target.0
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::{testing::*, Element};
+    use crate::{builder::*, bumpalo, Cached, Node, Render, RenderContext};
+
+    #[derive(Clone)]
+    struct Modal {
+        target: Element,
+        message: Option<&'static str>,
+    }
+
+    thread_local! {
+        static BODY: Element = Element::create_element("body");
+    }
+
+    // The same target for every `Modal` (including the template) on this test
+    // thread.
+    fn body() -> Element {
+        BODY.with(|body| body.clone())
+    }
+
+    impl Default for Modal {
+        fn default() -> Modal {
+            Modal {
+                target: body(),
+                message: Some("default"),
+            }
+        }
+    }
+
+    impl Render for Modal {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+            children.push(text("main"));
+            if let Some(message) = self.message {
+                let dialog = div(&cx)
+                    .attr("class", "modal")
+                    .on("click", |_, _, _| {})
+                    .children([text(message)])
+                    .finish();
+                children.push(portal(&cx, &self.target, dialog));
+            }
+            p(&cx).children(children).finish()
+        }
+    }
+
+    #[test]
+    fn portals() {
+        let body = body();
+        let modal = |message| Modal {
+            target: body.clone(),
+            message,
+        };
+
+        let (container, vdom) = mount(modal(Some("hello")));
+        assert_eq!(container.inner_html(), "<p>main<!--portal--></p>");
+        assert_eq!(body.inner_html(), "<div class=\"modal\">hello</div>");
+        let dialog = body.first_child().unwrap();
+        assert_eq!(dialog.event_listeners(), vec!["click".to_string()]);
+
+        // The portal's child is diffed in place.
+        vdom.immediately_render_and_diff(modal(Some("goodbye")));
+        assert_eq!(body.inner_html(), "<div class=\"modal\">goodbye</div>");
+        assert_eq!(body.first_child().unwrap(), dialog);
+
+        // Removing the portal removes its child from the target.
+        vdom.immediately_render_and_diff(modal(None));
+        assert_eq!(container.inner_html(), "<p>main</p>");
+        assert_eq!(body.inner_html(), "");
+
+        vdom.immediately_render_and_diff(modal(Some("again")));
+        assert_eq!(body.inner_html(), "<div class=\"modal\">again</div>");
+
+        // Unmounting removes portal children too.
+        drop(vdom);
+        assert_eq!(body.inner_html(), "");
+    }
+
+    #[test]
+    fn portals_in_cached_templates() {
+        let body = body();
+        let modal = |message| {
+            Cached::new(Modal {
+                target: body.clone(),
+                message: Some(message),
+            })
+        };
+
+        // Each `Cached<Modal>` is created by cloning the template, whose
+        // placeholder does not come with a portal child.
+        let (container, vdom) = mount(Div(vec![modal("a"), modal("b")]));
+        assert_eq!(
+            container.inner_html(),
+            "<div><p>main<!--portal--></p><p>main<!--portal--></p></div>"
+        );
+        assert_eq!(
+            body.inner_html(),
+            "<div class=\"modal\">a</div><div class=\"modal\">b</div>"
+        );
+
+        vdom.immediately_render_and_diff(Div(vec![modal("b")]));
+        assert_eq!(body.inner_html(), "<div class=\"modal\">b</div>");
+    }
+
+    // A `<li>` for each of the first `n` letters.
+    #[derive(Default)]
+    struct Items(usize);
+
+    impl Render for Items {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let mut children = bumpalo::collections::Vec::new_in(cx.bump);
+            for item in ["a", "b", "c"].iter().take(self.0) {
+                children.push(li(&cx).children([text(item)]).finish());
+            }
+            fragment(&cx, children)
+        }
+    }
+
+    // A portal of `Items`, followed by another portal into the same target.
+    struct Menu {
+        target: Element,
+        items: usize,
+    }
+
+    impl Render for Menu {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let items = Cached::new(Items(self.items)).render(cx);
+            let footer = p(&cx).children([text("footer")]).finish();
+            div(&cx)
+                .children([
+                    portal(&cx, &self.target, items),
+                    portal(&cx, &self.target, footer),
+                ])
+                .finish()
+        }
+    }
+
+    #[test]
+    fn portals_of_fragments() {
+        let target = Element::create_element("body");
+        let menu = |items| Menu {
+            target: target.clone(),
+            items,
+        };
+        let html = |items: &str| format!("{}<p>footer</p>", items);
+
+        let (container, vdom) = mount(menu(2));
+        assert_eq!(
+            container.inner_html(),
+            "<div><!--portal--><!--portal--></div>"
+        );
+        assert_eq!(target.inner_html(), html("<li>a</li><li>b</li>"));
+        let first = target.first_child().unwrap();
+
+        // New children are mounted after the existing ones, not after the
+        // other portal's child.
+        vdom.immediately_render_and_diff(menu(3));
+        assert_eq!(target.inner_html(), html("<li>a</li><li>b</li><li>c</li>"));
+        assert_eq!(target.first_child().unwrap(), first);
+
+        vdom.immediately_render_and_diff(menu(1));
+        assert_eq!(target.inner_html(), html("<li>a</li>"));
+        assert_eq!(target.first_child().unwrap(), first);
+
+        vdom.immediately_render_and_diff(menu(0));
+        assert_eq!(target.inner_html(), html(""));
+
+        // Without any children left to mount after, they are appended, like
+        // the child of a new portal.
+        vdom.immediately_render_and_diff(menu(2));
+        assert_eq!(target.inner_html(), "<p>footer</p><li>a</li><li>b</li>");
+
+        drop(vdom);
+        assert_eq!(target.inner_html(), "");
+    }
+}
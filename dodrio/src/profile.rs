@@ -0,0 +1,237 @@
+//! Opt-in profiling of renders.
+//!
+//! When profiling is enabled for a `Vdom`, each of its renders measures how
+//! long it spent in each phase and counts the work it did, and the resulting
+//! `RenderProfile`s are kept in a bounded history.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// A profile of a single render of a `Vdom`.
+///
+/// Get these with `Vdom::last_render_profile` and `Vdom::render_profiles`
+/// after enabling profiling with `Vdom::set_render_profiling`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderProfile {
+    pub(crate) render_time: Duration,
+    pub(crate) diff_time: Duration,
+    pub(crate) apply_time: Duration,
+    pub(crate) instructions: BTreeMap<&'static str, usize>,
+    pub(crate) strings_added: usize,
+    pub(crate) strings_dropped: usize,
+    pub(crate) cache_hits: usize,
+    pub(crate) cache_misses: usize,
+}
+
+impl RenderProfile {
+    /// The time spent in the root component's `Render::render`, including
+    /// the renders of all of its children.
+    pub fn render_time(&self) -> Duration {
+        self.render_time
+    }
+
+    /// The time spent diffing the new virtual DOM against the old one and
+    /// emitting the change list.
+    pub fn diff_time(&self) -> Duration {
+        self.diff_time
+    }
+
+    /// The time spent applying the change list to the physical DOM.
+    pub fn apply_time(&self) -> Duration {
+        self.apply_time
+    }
+
+    /// The total time spent rendering, diffing, and applying changes.
+    pub fn total_time(&self) -> Duration {
+        self.render_time + self.diff_time + self.apply_time
+    }
+
+    /// The number of change list instructions that were emitted, keyed by
+    /// opcode name (see `Instruction::name`).
+    ///
+    /// Opcodes that were not emitted at all are omitted.
+    pub fn instruction_counts(&self) -> &BTreeMap<&'static str, usize> {
+        &self.instructions
+    }
+
+    /// The total number of change list instructions that were emitted.
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.values().sum()
+    }
+
+    /// The number of strings that were added to the change list's strings
+    /// cache.
+    pub fn strings_added(&self) -> usize {
+        self.strings_added
+    }
+
+    /// The number of unused strings that were dropped from the change list's
+    /// strings cache.
+    pub fn strings_dropped(&self) -> usize {
+        self.strings_dropped
+    }
+
+    /// The number of `Cached<R>` components whose render was reused.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
+
+    /// The number of `Cached<R>` components that had to be re-rendered.
+    pub fn cache_misses(&self) -> usize {
+        self.cache_misses
+    }
+}
+
+/// A `Vdom`'s profiling settings and its rolling history of render profiles.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    capacity: usize,
+    history: VecDeque<RenderProfile>,
+}
+
+impl Profiler {
+    /// Is profiling enabled?
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Keep the profiles of the last `capacity` renders. Zero disables
+    /// profiling and clears the history.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.history.len() > capacity {
+            self.history.pop_front();
+        }
+    }
+
+    /// Record a render's profile, evicting the oldest one if the history is
+    /// full.
+    pub(crate) fn push(&mut self, profile: RenderProfile) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(profile);
+    }
+
+    /// The most recently recorded profile.
+    pub(crate) fn last(&self) -> Option<&RenderProfile> {
+        self.history.back()
+    }
+
+    /// The recorded profiles, oldest first.
+    pub(crate) fn history(&self) -> impl Iterator<Item = &RenderProfile> {
+        self.history.iter()
+    }
+}
+
+/// Measures elapsed time, but only while profiling, so that renders that are
+/// not being profiled don't pay for reading the clock.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Stopwatch(Option<Timestamp>);
+
+impl Stopwatch {
+    /// Start a stopwatch, which does nothing unless `enabled`.
+    pub(crate) fn start(enabled: bool) -> Stopwatch {
+        Stopwatch(if enabled {
+            Some(Timestamp::now())
+        } else {
+            None
+        })
+    }
+
+    /// The time that has passed since the stopwatch was started, or zero if it
+    /// is not enabled.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.0
+            .map_or(Duration::from_secs(0), |start| start.elapsed())
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
+        /// A point in time, for measuring how long something took.
+        #[derive(Clone, Copy, Debug)]
+        struct Timestamp(std::time::Instant);
+
+        impl Timestamp {
+            fn now() -> Timestamp {
+                Timestamp(std::time::Instant::now())
+            }
+
+            /// The time that has passed since this timestamp was taken.
+            fn elapsed(&self) -> Duration {
+                self.0.elapsed()
+            }
+        }
+    } else {
+        use wasm_bindgen::UnwrapThrowExt;
+
+        /// A point in time, for measuring how long something took.
+        #[derive(Clone, Copy, Debug)]
+        struct Timestamp(f64);
+
+        impl Timestamp {
+            fn now() -> Timestamp {
+                Timestamp(performance_now())
+            }
+
+            /// The time that has passed since this timestamp was taken.
+            fn elapsed(&self) -> Duration {
+                let millis = (performance_now() - self.0).max(0.0);
+                Duration::from_secs_f64(millis / 1000.0)
+            }
+        }
+
+        fn performance_now() -> f64 {
+            web_sys::window()
+                .expect_throw("should have a window")
+                .performance()
+                .expect_throw("should have access to `window.performance`")
+                .now()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::{testing::*, Element};
+    use crate::Vdom;
+
+    #[test]
+    fn render_profiles() {
+        let container = Element::create_element("div");
+        let vdom = Vdom::new(&container, list(&[1], false));
+        vdom.set_render_profiling(2);
+        assert_eq!(vdom.last_render_profile(), None);
+
+        vdom.immediately_render_and_diff(list(&[1, 2], false));
+        let profile = vdom.last_render_profile().unwrap();
+        assert_eq!(profile.instruction_counts()["create_element"], 1);
+        assert_eq!(profile.instruction_counts()["set_text"], 1);
+        assert_eq!(profile.strings_added(), 0);
+
+        vdom.immediately_render_and_diff(Div(vec![greeting("Alice")]));
+        let profile = vdom.last_render_profile().unwrap();
+        assert_eq!((profile.cache_hits(), profile.cache_misses()), (0, 1));
+        // Added "div" and "p", and dropped "ul" and "li".
+        assert_eq!(profile.strings_added(), 2);
+        assert_eq!(profile.strings_dropped(), 2);
+
+        // Re-rendering the same component reuses its cached greeting.
+        vdom.weak().render_sync().unwrap();
+        let profile = vdom.last_render_profile().unwrap();
+        assert_eq!((profile.cache_hits(), profile.cache_misses()), (1, 0));
+        assert!(!profile.instruction_counts().contains_key("create_element"));
+
+        let profiles = vdom.weak().render_profiles().unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[1], profile);
+
+        vdom.set_render_profiling(0);
+        assert!(vdom.render_profiles().is_empty());
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{
-    cached::{Cached, TemplateId},
+    cached::TemplateId,
     cached_set::{CacheId, CachedSet},
+    signal::TrackingScope,
     Node, Render,
 };
 use bumpalo::Bump;
 use fxhash::FxHashMap;
+use std::any::TypeId;
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 
 /// Common context available to all `Render` implementations.
 ///
@@ -30,6 +33,11 @@ pub struct RenderContext<'a> {
 
     pub(crate) templates: &'a mut FxHashMap<TemplateId, Option<CacheId>>,
 
+    // The values that are currently provided with `provide`, innermost last.
+    // Each pointer is only valid until the `provide` call that pushed it
+    // returns, which also pops it.
+    pub(crate) context: Vec<(TypeId, *const ())>,
+
     // Prevent exhaustive matching on the rendering context, so we can always
     // add more members in a semver-compatible way.
     _non_exhaustive: (),
@@ -54,11 +62,95 @@ impl<'a> RenderContext<'a> {
                 bump,
                 cached_set,
                 templates,
+                context: Vec::new(),
                 _non_exhaustive: (),
             }
         }
     }
 
+    /// Provide `value` to every component that is rendered by `f`.
+    ///
+    /// Inside `f`, `get::<T>()` returns `value`, including inside the renders
+    /// of nested `Cached` components. Providing another `T` inside `f` shadows
+    /// this one until it is no longer provided.
+    ///
+    /// A `Cached` component's render is reused until it is invalidated, so it
+    /// must be invalidated when the context values that it reads change, just
+    /// like when its own state changes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{builder::*, bumpalo, Cached, Node, Render, RenderContext};
+    ///
+    /// struct AppData {
+    ///     title: String,
+    /// }
+    ///
+    /// #[derive(Default)]
+    /// struct Header;
+    ///
+    /// impl Render for Header {
+    ///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+    ///         let title = match cx.get::<AppData>() {
+    ///             Some(app_data) => bumpalo::format!(in cx.bump, "{}", app_data.title),
+    ///             None => bumpalo::collections::String::new_in(cx.bump),
+    ///         };
+    ///         h1(&cx).children([text(title.into_bump_str())]).finish()
+    ///     }
+    /// }
+    ///
+    /// struct App {
+    ///     app_data: AppData,
+    ///     header: Cached<Header>,
+    /// }
+    ///
+    /// impl Render for App {
+    ///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+    ///         cx.provide(&self.app_data, |cx| {
+    ///             div(&cx).children([self.header.render(cx)]).finish()
+    ///         })
+    ///     }
+    /// }
+    /// ```
+    pub fn provide<T, F, U>(&mut self, value: &T, f: F) -> U
+    where
+        T: 'static,
+        F: FnOnce(&mut RenderContext<'a>) -> U,
+    {
+        let depth = self.context.len();
+        self.context
+            .push((TypeId::of::<T>(), value as *const T as *const ()));
+
+        // Pop the value even if `f` panics, because the panic might be caught
+        // by an `error_boundary` that keeps rendering with this context.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        self.context.truncate(depth);
+        match result {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Get the innermost value of type `T` that is provided with `provide`, if
+    /// any.
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        self.context
+            .iter()
+            .rev()
+            .find(|(id, _)| *id == type_id)
+            .map(|&(_, value)| unsafe {
+                // Safety relies on `provide` popping the value before its
+                // borrow ends, and on our returned borrow of `self` preventing
+                // that from happening while the returned reference is alive.
+                &*(value as *const T)
+            })
+    }
+
     pub(crate) fn cache<F>(&mut self, pinned: bool, template: Option<CacheId>, f: F) -> CacheId
     where
         F: for<'b> FnOnce(&mut RenderContext<'b>) -> Node<'b>,
@@ -66,12 +158,16 @@ impl<'a> RenderContext<'a> {
         CachedSet::insert(self, pinned, template, f)
     }
 
-    /// Get or create the cached template for `Cached<R>`.
-    pub(crate) fn template<R>(&mut self) -> Option<CacheId>
+    /// Get or create the cached template with the given id, which is rendered
+    /// from the `R` that `prototype` constructs.
+    pub(crate) fn template<R>(
+        &mut self,
+        template_id: TemplateId,
+        prototype: fn() -> R,
+    ) -> Option<CacheId>
     where
-        R: 'static + Default + Render,
+        R: Render,
     {
-        let template_id = Cached::<R>::template_id();
         if let Some(cache_id) = self.templates.get(&template_id).cloned() {
             return cache_id;
         }
@@ -81,11 +177,26 @@ impl<'a> RenderContext<'a> {
         // templated fast path.
         self.templates.insert(template_id, None);
 
-        // Render the default `R` and save that as the template for all
-        // `Cached<R>`s.
-        let cache_id = self.cache(true, None, |nested_cx| R::default().render(nested_cx));
-        self.templates.insert(template_id, Some(cache_id));
-        Some(cache_id)
+        // Render the prototype `R` and save that as the template for all
+        // `Cached<R>`s with this template id.
+        let cache_id = self.cache(true, None, |nested_cx| {
+            let _tracking = TrackingScope::untracked();
+            prototype().render(nested_cx)
+        });
+
+        // A template is cloned as a single physical DOM node, so a fragment
+        // can't be one. Render without a template instead.
+        let cache_id = {
+            let mut cached_set = self.cached_set.borrow_mut();
+            if cached_set.is_fragment(cached_set.get(cache_id).0) {
+                cached_set.unpin(cache_id);
+                None
+            } else {
+                Some(cache_id)
+            }
+        };
+        self.templates.insert(template_id, cache_id);
+        cache_id
     }
 }
 
@@ -102,3 +213,53 @@ impl<'a, 'b, 'c> From<&'c &'b mut RenderContext<'a>> for &'a Bump {
         cx.bump
     }
 }
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use super::RenderContext;
+    use crate::headless::testing::*;
+    use crate::{builder::*, Cached, Node, Render};
+
+    struct AppData {
+        title: &'static str,
+    }
+
+    #[derive(Default)]
+    struct Title;
+
+    impl Render for Title {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let title = cx.get::<AppData>().map_or("untitled", |data| data.title);
+            h1(&cx).children([text(title)]).finish()
+        }
+    }
+
+    struct App {
+        app_data: Option<AppData>,
+        title: Cached<Title>,
+    }
+
+    impl Render for App {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            match &self.app_data {
+                Some(app_data) => cx.provide(app_data, |cx| self.title.render(cx)),
+                None => self.title.render(cx),
+            }
+        }
+    }
+
+    #[test]
+    fn context() {
+        let (container, vdom) = mount(App {
+            app_data: Some(AppData { title: "Hello" }),
+            title: Cached::new(Title),
+        });
+        assert_eq!(container.inner_html(), "<h1>Hello</h1>");
+
+        vdom.immediately_render_and_diff(App {
+            app_data: None,
+            title: Cached::new(Title),
+        });
+        assert_eq!(container.inner_html(), "<h1>untitled</h1>");
+    }
+}
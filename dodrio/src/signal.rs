@@ -0,0 +1,335 @@
+//! Reactive cells whose reads are tracked while rendering.
+//!
+//! While a `Vdom` renders, we maintain a stack of tracking frames: one for the
+//! root component, and one more for each `Cached<R>` that is being re-rendered
+//! inside it. When a `Signal` is read, every frame on the stack is recorded as
+//! one of its dependents, since all of their renders now depend on its value.
+//! When the `Signal` is written, its dependent `Cached<R>`s are invalidated and
+//! their `Vdom`s are scheduled to re-render.
+
+use crate::cached::{bump_generation, Generation};
+use crate::vdom::VdomWeak;
+use fxhash::FxHashMap;
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+enum Frame {
+    // The reads in this frame are recorded against this dependent, and the
+    // frames below it.
+    Tracked(Dependent),
+    // The reads in this frame, and the frames above it, are not recorded.
+    Untracked,
+}
+
+thread_local! {
+    // The tracking frames for the renders that are currently in progress.
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Pops its tracking frame when dropped, even if rendering panics.
+#[derive(Debug)]
+pub(crate) struct TrackingScope {
+    _private: (),
+}
+
+impl TrackingScope {
+    fn push(frame: Frame) -> TrackingScope {
+        FRAMES.with(|frames| frames.borrow_mut().push(frame));
+        TrackingScope { _private: () }
+    }
+
+    /// Track the reads made while rendering the given `Vdom`'s root
+    /// component.
+    pub(crate) fn root(vdom: VdomWeak) -> TrackingScope {
+        TrackingScope::push(Frame::Tracked(Dependent {
+            vdom: Some(vdom),
+            cached: None,
+        }))
+    }
+
+    /// Track the reads made while re-rendering a `Cached<R>`, on behalf of the
+    /// `Vdom` that is currently rendering, if any.
+    pub(crate) fn cached(generation: &Generation) -> TrackingScope {
+        let vdom = FRAMES.with(|frames| match frames.borrow().last() {
+            Some(Frame::Tracked(dependent)) => dependent.vdom.clone(),
+            _ => None,
+        });
+        TrackingScope::push(Frame::Tracked(Dependent {
+            vdom,
+            cached: Some(Rc::downgrade(generation)),
+        }))
+    }
+
+    /// Don't track the reads made inside this scope. Used when rendering a
+    /// template, whose output never depends on the app's state.
+    pub(crate) fn untracked() -> TrackingScope {
+        TrackingScope::push(Frame::Untracked)
+    }
+}
+
+impl Drop for TrackingScope {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+// Something that must be notified when a signal is written.
+#[derive(Clone)]
+struct Dependent {
+    vdom: Option<VdomWeak>,
+    // The `Cached<R>`'s generation counter, or `None` for a root component.
+    cached: Option<Weak<Cell<u32>>>,
+}
+
+impl Dependent {
+    // Identify dependents by the addresses of their `Cached<R>`'s generation
+    // counter and their `Vdom`, so that re-rendering doesn't record duplicates.
+    fn key(&self) -> (usize, usize) {
+        let cached = self.cached.as_ref().map_or(0, |c| c.as_ptr() as usize);
+        let vdom = self.vdom.as_ref().map_or(0, VdomWeak::as_ptr);
+        (cached, vdom)
+    }
+}
+
+/// A reactive cell of shared app state.
+///
+/// When a `Signal` is read while rendering, the read is recorded against the
+/// `Cached<R>` components that are being rendered, and against the `Vdom` that
+/// is rendering them. Writing the `Signal` then invalidates exactly those
+/// `Cached<R>` components, and schedules their `Vdom`s to re-render. There is
+/// no need to call `Cached::invalidate` or `VdomWeak::schedule_render`
+/// yourself.
+///
+/// `Signal` is a cheap, reference-counted handle: clones refer to the same
+/// value, so a single `Signal` can be shared by many components.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, bumpalo, Cached, Node, Render, RenderContext, Signal};
+///
+/// #[derive(Default)]
+/// struct Header {
+///     title: Signal<String>,
+/// }
+///
+/// impl Render for Header {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         // This read makes any `Cached<Header>` that renders it depend on
+///         // `title`.
+///         let title = self.title.with(|title| bumpalo::format!(in cx.bump, "{}", title));
+///         h1(&cx).children([text(title.into_bump_str())]).finish()
+///     }
+/// }
+///
+/// struct App {
+///     title: Signal<String>,
+///     header: Cached<Header>,
+/// }
+///
+/// impl Render for App {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let title = self.title.clone();
+///         div(&cx)
+///             .children([
+///                 self.header.render(cx),
+///                 button(&cx)
+///                     .on("click", move |_root, _vdom, _event| {
+///                         // Invalidates the `Cached<Header>` and schedules a
+///                         // re-render.
+///                         title.set("Clicked!".to_string());
+///                     })
+///                     .children([text("Click me")])
+///                     .finish(),
+///             ])
+///             .finish()
+///     }
+/// }
+/// ```
+pub struct Signal<T> {
+    inner: Rc<SignalInner<T>>,
+}
+
+struct SignalInner<T> {
+    value: RefCell<T>,
+    dependents: RefCell<FxHashMap<(usize, usize), Dependent>>,
+}
+
+impl<T> Clone for Signal<T> {
+    fn clone(&self) -> Signal<T> {
+        Signal {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Signal<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Signal")
+            .field("value", &self.inner.value)
+            .field("dependents", &self.inner.dependents.borrow().len())
+            .finish()
+    }
+}
+
+impl<T> Default for Signal<T>
+where
+    T: Default,
+{
+    fn default() -> Signal<T> {
+        Signal::new(T::default())
+    }
+}
+
+impl<T> Signal<T> {
+    /// Construct a new `Signal` with the given initial value.
+    pub fn new(value: T) -> Signal<T> {
+        Signal {
+            inner: Rc::new(SignalInner {
+                value: RefCell::new(value),
+                dependents: RefCell::new(FxHashMap::default()),
+            }),
+        }
+    }
+
+    /// Get a copy of the current value, and track the read.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    /// Invoke `f` with a reference to the current value, and track the read.
+    pub fn with<F, U>(&self, f: F) -> U
+    where
+        F: FnOnce(&T) -> U,
+    {
+        self.track();
+        f(&self.inner.value.borrow())
+    }
+
+    /// Borrow the current value *without* tracking the read.
+    ///
+    /// Components that only read the value this way are not re-rendered when
+    /// it changes.
+    pub fn borrow_untracked(&self) -> Ref<'_, T> {
+        self.inner.value.borrow()
+    }
+
+    /// Replace the value, invalidate the `Cached<R>` components that read it,
+    /// and schedule their virtual DOMs to re-render.
+    pub fn set(&self, value: T) {
+        self.update(|v| *v = value);
+    }
+
+    /// Mutate the value in place with `f`, invalidate the `Cached<R>`
+    /// components that read it, and schedule their virtual DOMs to re-render.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut T),
+    {
+        f(&mut self.inner.value.borrow_mut());
+        self.notify();
+    }
+
+    // Record the read against every render that is in progress.
+    fn track(&self) {
+        FRAMES.with(|frames| {
+            let mut dependents = self.inner.dependents.borrow_mut();
+            for frame in frames.borrow().iter().rev() {
+                match frame {
+                    Frame::Tracked(dependent) => {
+                        dependents
+                            .entry(dependent.key())
+                            .or_insert_with(|| dependent.clone());
+                    }
+                    Frame::Untracked => break,
+                }
+            }
+        });
+    }
+
+    // Invalidate and re-render everything that depends on this signal. The
+    // dependents will be recorded again when they re-render.
+    fn notify(&self) {
+        let dependents = std::mem::take(&mut *self.inner.dependents.borrow_mut());
+        let mut vdoms: Vec<VdomWeak> = vec![];
+        for (_, dependent) in dependents {
+            if let Some(generation) = dependent.cached.as_ref().and_then(Weak::upgrade) {
+                bump_generation(&generation);
+            }
+            if let Some(vdom) = dependent.vdom {
+                if !vdoms.iter().any(|v| v.as_ptr() == vdom.as_ptr()) {
+                    vdoms.push(vdom);
+                }
+            }
+        }
+        for vdom in vdoms {
+            schedule_render(&vdom);
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
+        // The headless backend has no animation frames to render in, so
+        // headless users must render explicitly, e.g. with
+        // `VdomWeak::render_sync`.
+        fn schedule_render(_vdom: &VdomWeak) {}
+    } else {
+        fn schedule_render(vdom: &VdomWeak) {
+            vdom.schedule_render();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use super::Signal;
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Cached, Node, Render, RenderContext};
+
+    #[derive(Default)]
+    struct Label {
+        text: Signal<String>,
+    }
+
+    impl Render for Label {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let label = self
+                .text
+                .with(|t| bumpalo::collections::String::from_str_in(t, cx.bump));
+            p(&cx).children([text(label.into_bump_str())]).finish()
+        }
+    }
+
+    #[test]
+    fn signals_invalidate_their_dependents() {
+        let a = Signal::new("a".to_string());
+        let b = Signal::new("b".to_string());
+        let label = |text: &Signal<String>| Cached::new(Label { text: text.clone() });
+
+        let (container, vdom) = mount(Div(vec![label(&a), label(&b)]));
+        assert_eq!(container.inner_html(), "<div><p>a</p><p>b</p></div>");
+
+        // Only the label that read `a` is re-rendered.
+        a.set("A".to_string());
+        vdom.weak().render_sync().unwrap();
+        assert_eq!(container.inner_html(), "<div><p>A</p><p>b</p></div>");
+        assert_eq!(cache_hits_and_misses(&vdom), (1, 1));
+
+        // The label that read `b` during the first render still depends on it,
+        // even though it was not re-rendered since.
+        b.update(|b| b.push('!'));
+        vdom.weak().render_sync().unwrap();
+        assert_eq!(container.inner_html(), "<div><p>A</p><p>b!</p></div>");
+
+        vdom.weak().render_sync().unwrap();
+        assert_eq!(cache_hits_and_misses(&vdom), (2, 0));
+    }
+}
@@ -0,0 +1,87 @@
+//! Adapting futures 0.1 futures into `std::future::Future`s, so that they can
+//! be `.await`ed.
+
+use futures::executor::{self, Notify, NotifyHandle, Spawn};
+use futures::{Async, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// A `std::future::Future` that polls an inner futures 0.1 future.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub(crate) struct StdFuture<F> {
+    inner: Spawn<F>,
+}
+
+impl<F> StdFuture<F>
+where
+    F: Future,
+{
+    pub(crate) fn new(future: F) -> StdFuture<F> {
+        StdFuture {
+            inner: executor::spawn(future),
+        }
+    }
+}
+
+// Futures 0.1 futures are never pinned, so they may be moved at any time.
+impl<F> Unpin for StdFuture<F> {}
+
+impl<F> std::future::Future for StdFuture<F>
+where
+    F: Future,
+{
+    type Output = Result<F::Item, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let notify = NotifyHandle::from(Arc::new(WakerNotify(cx.waker().clone())));
+        match self.get_mut().inner.poll_future_notify(&notify, 0) {
+            Ok(Async::Ready(item)) => Poll::Ready(Ok(item)),
+            Ok(Async::NotReady) => Poll::Pending,
+            Err(error) => Poll::Ready(Err(error)),
+        }
+    }
+}
+
+// Wakes the `std::future::Future`'s task when the inner future is notified.
+struct WakerNotify(Waker);
+
+impl Notify for WakerNotify {
+    fn notify(&self, _id: usize) {
+        self.0.wake_by_ref();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StdFuture;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wakes_when_the_inner_future_is_notified() {
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let (sender, receiver) = futures::sync::oneshot::channel::<u32>();
+        let mut future = StdFuture::new(receiver);
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        sender.send(42).unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Ok(42)));
+    }
+}
@@ -0,0 +1,265 @@
+//! Elm-style message dispatch to the root rendering component.
+//!
+//! Listeners created with `ElementBuilder::on_msg` don't know the concrete type
+//! of the root rendering component, so they can't call its `Update::update`
+//! method themselves. Instead, a `Vdom` that is mounted with `Vdom::with_update`
+//! or `Vdom::hydrate_with_update` remembers a dispatcher function that was
+//! monomorphized for its root's type, and listeners send their messages through
+//! it.
+
+use crate::{RootRender, VdomWeak};
+use std::any::{self, Any};
+
+/// Whether the virtual DOM should be re-rendered after handling a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShouldRender {
+    /// The message changed the component's state, so schedule a re-render.
+    Yes,
+
+    /// Nothing that is rendered changed, so there is no need to re-render.
+    No,
+}
+
+/// A root rendering component that is updated by messages, rather than by
+/// listeners that downcast it to its concrete type.
+///
+/// Mount an `Update` component with `Vdom::with_update`, or hydrate it with
+/// `Vdom::hydrate_with_update`, and then use `ElementBuilder::on_msg` to create
+/// listeners that send it messages. The components that create those listeners
+/// only need to know about the message type, not the root component's type.
+///
+/// # Example
+///
+/// ```no_run
+/// use dodrio::{builder::*, bumpalo, Node, Render, RenderContext, ShouldRender, Update};
+///
+/// pub enum Msg {
+///     Increment,
+///     Reset,
+/// }
+///
+/// // A reusable component that knows about the messages, but not the root.
+/// struct Buttons;
+///
+/// impl Render for Buttons {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         div(&cx)
+///             .children([
+///                 button(&cx)
+///                     .on_msg("click", |_event| Msg::Increment)
+///                     .children([text("+")])
+///                     .finish(),
+///                 button(&cx)
+///                     .on_msg("click", |_event| Msg::Reset)
+///                     .children([text("Reset")])
+///                     .finish(),
+///             ])
+///             .finish()
+///     }
+/// }
+///
+/// struct Counter {
+///     count: u32,
+/// }
+///
+/// impl Render for Counter {
+///     fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+///         let count = bumpalo::format!(in cx.bump, "{}", self.count);
+///         div(&cx)
+///             .children([text(count.into_bump_str()), Buttons.render(cx)])
+///             .finish()
+///     }
+/// }
+///
+/// impl Update for Counter {
+///     type Msg = Msg;
+///
+///     fn update(&mut self, msg: Msg) -> ShouldRender {
+///         match msg {
+///             Msg::Increment => self.count += 1,
+///             Msg::Reset if self.count == 0 => return ShouldRender::No,
+///             Msg::Reset => self.count = 0,
+///         }
+///         ShouldRender::Yes
+///     }
+/// }
+///
+/// # fn mount(container: &dodrio::Element) {
+/// let vdom = dodrio::Vdom::with_update(container, Counter { count: 0 });
+/// # }
+/// ```
+pub trait Update: RootRender {
+    /// The type of messages that this component handles.
+    type Msg: 'static;
+
+    /// Handle a message, and return whether the virtual DOM should be
+    /// re-rendered.
+    fn update(&mut self, msg: Self::Msg) -> ShouldRender;
+}
+
+/// Delivers a type-erased message to a type-erased root rendering component.
+pub(crate) type Dispatcher = fn(&mut dyn RootRender, Box<dyn Any>) -> ShouldRender;
+
+/// The dispatcher for `R` roots.
+pub(crate) fn dispatch<R>(root: &mut dyn RootRender, msg: Box<dyn Any>) -> ShouldRender
+where
+    R: Update,
+{
+    let msg = match msg.downcast::<R::Msg>() {
+        Ok(msg) => *msg,
+        Err(_) => {
+            error!(
+                "on_msg: the root rendering component handles `{}` messages, not this message",
+                any::type_name::<R::Msg>(),
+            );
+            return ShouldRender::No;
+        }
+    };
+    match root.as_any_mut().downcast_mut::<R>() {
+        Some(root) => root.update(msg),
+        None => {
+            error!(
+                "on_msg: the root rendering component is no longer a `{}`",
+                any::type_name::<R>(),
+            );
+            ShouldRender::No
+        }
+    }
+}
+
+/// Send a message to the given virtual DOM's root rendering component, and
+/// schedule a re-render if it asks for one.
+pub(crate) fn send(root: &mut dyn RootRender, vdom: &VdomWeak, msg: Box<dyn Any>) {
+    match vdom.dispatcher() {
+        None => error!(
+            "on_msg: the root rendering component does not handle messages; mount it with \
+             `Vdom::with_update`"
+        ),
+        Some(dispatch) => {
+            if dispatch(root, msg) == ShouldRender::Yes {
+                vdom.schedule_render();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dispatch, ShouldRender, Update};
+    use crate::{builder::*, bumpalo, Node, RenderContext, RootRender};
+
+    struct Counter(u32);
+
+    impl crate::Render for Counter {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let count = bumpalo::format!(in cx.bump, "{}", self.0);
+            div(&cx)
+                .children([
+                    text(count.into_bump_str()),
+                    button(&cx).on_msg("click", |_| 1_u32).finish(),
+                    button(&cx).on_msg("click", |_| 0_u32).finish(),
+                ])
+                .finish()
+        }
+    }
+
+    impl Update for Counter {
+        type Msg = u32;
+
+        fn update(&mut self, msg: u32) -> ShouldRender {
+            self.0 += msg;
+            if msg == 0 {
+                ShouldRender::No
+            } else {
+                ShouldRender::Yes
+            }
+        }
+    }
+
+    #[test]
+    fn dispatches_messages_of_the_roots_type() {
+        let mut counter = Counter(0);
+        let root: &mut dyn RootRender = &mut counter;
+        assert_eq!(
+            dispatch::<Counter>(root, Box::new(2_u32)),
+            ShouldRender::Yes
+        );
+        assert_eq!(dispatch::<Counter>(root, Box::new(0_u32)), ShouldRender::No);
+
+        // Messages of other types are dropped.
+        assert_eq!(dispatch::<Counter>(root, Box::new("2")), ShouldRender::No);
+        assert_eq!(root.unwrap_ref::<Counter>().0, 2);
+    }
+
+    #[cfg(feature = "xxx-unstable-internal-use-only")]
+    mod headless {
+        use super::Counter;
+        use crate::{headless::Element, Vdom};
+
+        fn buttons(container: &Element) -> (Element, Element) {
+            let children = container.first_child().unwrap().child_nodes();
+            (children[1].clone(), children[2].clone())
+        }
+
+        fn mount_with_update(container: &Element, hydrate: bool) -> Vdom {
+            let vdom = if hydrate {
+                Vdom::hydrate_with_update(container, Counter(0))
+            } else {
+                Vdom::with_update(container, Counter(0))
+            };
+            vdom.set_render_profiling(10);
+            vdom
+        }
+
+        #[test]
+        fn messages_that_should_render_rerender() {
+            let container = Element::create_element("div");
+            let vdom = mount_with_update(&container, false);
+            let (increment, _) = buttons(&container);
+
+            vdom.dispatch_event(&increment, "click");
+            vdom.dispatch_event(&increment, "click");
+            assert_eq!(vdom.render_profiles().len(), 2);
+            assert_eq!(
+                container.inner_html(),
+                "<div>2<button></button><button></button></div>"
+            );
+        }
+
+        #[test]
+        fn messages_that_should_not_render_do_not_rerender() {
+            let container = Element::create_element("div");
+            let vdom = mount_with_update(&container, false);
+            let (_, noop) = buttons(&container);
+
+            vdom.dispatch_event(&noop, "click");
+            assert!(vdom.render_profiles().is_empty());
+            assert_eq!(
+                container.inner_html(),
+                "<div>0<button></button><button></button></div>"
+            );
+        }
+
+        #[test]
+        fn hydrated_roots_handle_messages() {
+            // Build the markup that `render_to_string` would have produced.
+            let container = Element::create_element("div");
+            let div = Element::create_element("div");
+            div.append_child(&Element::create_text_node("0"));
+            div.append_child(&Element::create_element("button"));
+            div.append_child(&Element::create_element("button"));
+            container.append_child(&div);
+
+            let vdom = mount_with_update(&container, true);
+            assert_eq!(container.first_child().unwrap(), div);
+            let (increment, _) = buttons(&container);
+
+            vdom.dispatch_event(&increment, "click");
+            assert_eq!(vdom.render_profiles().len(), 1);
+            assert_eq!(
+                container.inner_html(),
+                "<div>1<button></button><button></button></div>"
+            );
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use super::change_list::ChangeListPersistentState;
 use super::RootRender;
 use crate::cached::TemplateId;
-use crate::cached_set::{CacheId, CachedSet};
+use crate::cached_set::{CacheId, CacheStats, CachedSet};
 use crate::events::EventsRegistry;
+use crate::lifecycle::LifecycleHooks;
 use crate::node::{Node, NodeKey};
+use crate::profile::{Profiler, RenderProfile, Stopwatch};
+use crate::signal::TrackingScope;
+use crate::std_future::StdFuture;
+use crate::update::{Dispatcher, Update};
 use crate::RenderContext;
 use bumpalo::Bump;
 use futures::future::Future;
@@ -14,6 +19,7 @@ use std::fmt;
 use std::mem;
 use std::mem::ManuallyDrop;
 use std::rc::{Rc, Weak};
+use std::slice;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
@@ -48,7 +54,31 @@ pub(crate) struct VdomInner {
 }
 
 pub(crate) struct VdomInnerShared {
-    pub(crate) render_scheduled: Cell<Option<js_sys::Promise>>,
+    pub(crate) render_scheduled: Cell<Option<ScheduledRender>>,
+
+    // Shared rather than exclusive, so that profiles can be inspected from
+    // inside event listeners and lifecycle hooks.
+    pub(crate) profiler: RefCell<Profiler>,
+
+    // Set when the root rendering component handles messages.
+    pub(crate) dispatcher: Cell<Option<Dispatcher>>,
+
+    // The headless backend has no animation frames, so scheduled renders are
+    // remembered here until `Vdom::dispatch_event` performs them.
+    #[cfg(all(
+        feature = "xxx-unstable-internal-use-only",
+        not(target_arch = "wasm32")
+    ))]
+    pub(crate) render_requested: Cell<bool>,
+}
+
+/// A render that has been scheduled for the next animation frame.
+#[derive(Debug)]
+pub(crate) struct ScheduledRender {
+    // Resolved once the render has happened.
+    promise: js_sys::Promise,
+    resolve: js_sys::Function,
+    frame: AnimationFrame,
 }
 
 pub(crate) struct VdomInnerExclusive {
@@ -65,6 +95,10 @@ pub(crate) struct VdomInnerExclusive {
     cached_set: crate::RefCell<CachedSet>,
     templates: FxHashMap<TemplateId, Option<CacheId>>,
 
+    // A weak reference to the `VdomInner` that owns this, for passing to
+    // lifecycle hooks.
+    vdom: Weak<VdomInner>,
+
     // Actually a reference into `self.dom_buffers[0]` or if `self.component` is
     // caching renders, into `self.component`'s bump.
     current_root: Option<Node<'static>>,
@@ -82,6 +116,8 @@ impl fmt::Debug for VdomInnerShared {
         let r = f
             .debug_struct("VdomInnerShared")
             .field("render_scheduled", &render_scheduled)
+            .field("profiler", &self.profiler)
+            .field("dispatcher", &self.dispatcher)
             .finish();
         self.render_scheduled.set(render_scheduled);
         r
@@ -106,15 +142,24 @@ impl Drop for VdomInnerExclusive {
     fn drop(&mut self) {
         debug!("Dropping VdomInnerExclusive");
 
+        // If the component hasn't been taken by `Vdom::unmount`, which does
+        // this itself, let everything that is rendered know that it is going
+        // away.
+        if self.component.is_some() {
+            self.run_unmount_hooks();
+        }
+
         // Make sure that we clean up our JS listeners and all that before we
         // empty the container.
         unsafe {
             ManuallyDrop::drop(&mut self.change_list);
         }
 
-        let registry = self.events_registry.take().unwrap_throw();
-        let mut registry = registry.borrow_mut();
-        registry.clear_active_listeners();
+        // The registry is only missing if a render panicked, e.g. because of
+        // a failed `debug-checks` validation, and we are unwinding.
+        if let Some(registry) = self.events_registry.take() {
+            registry.borrow_mut().clear_active_listeners();
+        }
 
         empty_container(&self.container);
     }
@@ -122,8 +167,16 @@ impl Drop for VdomInnerExclusive {
 
 cfg_if::cfg_if! {
     if #[cfg(all(feature = "xxx-unstable-internal-use-only", not(target_arch = "wasm32")))] {
-        fn empty_container(_container: &crate::Element) {}
-        fn initialize_container(_container: &crate::Element) {}
+        fn empty_container(container: &crate::Element) {
+            container.set_text_content("");
+        }
+
+        fn initialize_container(container: &crate::Element) {
+            empty_container(container);
+
+            // Create the dummy `<div/>` child in the container.
+            container.append_child(&crate::headless::Element::create_element("div"));
+        }
     } else {
         fn empty_container(container: &crate::Element) {
             container.set_inner_html("");
@@ -164,21 +217,105 @@ impl Vdom {
     /// Construct a `Vdom` with the already-boxed-as-a-trait-object root
     /// rendering component.
     pub fn with_boxed_root_render(container: &crate::Element, component: Box<RootRender>) -> Vdom {
+        Self::mount(container, component, false, None)
+    }
+
+    /// Mount a new `Vdom` in the given container element with the given root
+    /// rendering component, which handles the messages that are sent by
+    /// `ElementBuilder::on_msg` listeners.
+    ///
+    /// To hydrate server-rendered markup instead, use
+    /// `Vdom::hydrate_with_update`.
+    pub fn with_update<R>(container: &crate::Element, component: R) -> Vdom
+    where
+        R: Update,
+    {
+        Self::mount(
+            container,
+            Box::new(component) as Box<dyn RootRender>,
+            false,
+            Some(crate::update::dispatch::<R>),
+        )
+    }
+
+    /// Mount a new `Vdom` in the given container element, hydrating the
+    /// markup that is already inside the container instead of building the
+    /// physical DOM from scratch.
+    ///
+    /// This is intended for containers whose contents were rendered on the
+    /// server with `dodrio::render_to_string`. The existing physical nodes are
+    /// reused and event listeners are attached to them. Change list
+    /// instructions are only emitted where the existing markup does not match
+    /// the component's first render, and each such mismatch is logged as a
+    /// warning.
+    ///
+    /// If the container is empty, this is equivalent to `Vdom::new`. Roots
+    /// that implement `Update` should be mounted with
+    /// `Vdom::hydrate_with_update` instead.
+    pub fn hydrate<R>(container: &crate::Element, component: R) -> Vdom
+    where
+        R: RootRender,
+    {
+        Self::mount(
+            container,
+            Box::new(component) as Box<dyn RootRender>,
+            true,
+            None,
+        )
+    }
+
+    /// Like `Vdom::hydrate`, but the root rendering component handles the
+    /// messages that are sent by `ElementBuilder::on_msg` listeners, like with
+    /// `Vdom::with_update`.
+    pub fn hydrate_with_update<R>(container: &crate::Element, component: R) -> Vdom
+    where
+        R: Update,
+    {
+        Self::mount(
+            container,
+            Box::new(component) as Box<dyn RootRender>,
+            true,
+            Some(crate::update::dispatch::<R>),
+        )
+    }
+
+    fn mount(
+        container: &crate::Element,
+        component: Box<dyn RootRender>,
+        hydrate: bool,
+        dispatcher: Option<Dispatcher>,
+    ) -> Vdom {
         crate::strace::init_strace();
 
         let dom_buffers = [Bump::new(), Bump::new()];
         let change_list = ManuallyDrop::new(ChangeListPersistentState::new(container));
 
-        // Create a dummy `<div/>` in our container.
-        initialize_container(container);
-        let current_root =
-            Node::element(&dom_buffers[0], NodeKey::NONE, "div", &[], &[], &[], None);
-        let current_root = Some(unsafe { extend_node_lifetime(current_root) });
+        let current_root = match crate::hydrate::first_child(container) {
+            Some(_) if hydrate => {
+                // No current root: the first render hydrates the container's
+                // existing children instead of diffing.
+                None
+            }
+            _ => {
+                // Create a dummy `<div/>` in our container.
+                initialize_container(container);
+                let current_root =
+                    Node::element(&dom_buffers[0], NodeKey::NONE, "div", &[], &[], &[], None);
+                Some(unsafe { extend_node_lifetime(current_root) })
+            }
+        };
 
         let container = container.clone();
         let inner = Rc::new(VdomInner {
             shared: VdomInnerShared {
                 render_scheduled: Cell::new(None),
+                profiler: RefCell::new(Profiler::default()),
+                dispatcher: Cell::new(dispatcher),
+                #[cfg(all(
+                    feature = "xxx-unstable-internal-use-only",
+                    not(target_arch = "wasm32")
+                ))]
+                render_requested: Cell::new(false),
             },
             exclusive: RefCell::new(VdomInnerExclusive {
                 component: Some(component),
@@ -190,19 +327,23 @@ impl Vdom {
                 events_trampoline: None,
                 cached_set: crate::RefCell::new(Default::default()),
                 templates: Default::default(),
+                vdom: Weak::new(),
             }),
         });
 
         let (events_registry, events_trampoline) = EventsRegistry::new(Rc::downgrade(&inner));
+        let vdom = Rc::downgrade(&inner);
 
         {
             let mut inner = inner.exclusive.borrow_mut();
+            inner.vdom = vdom;
             inner.events_registry = Some(events_registry);
             inner.change_list.init_events_trampoline(&events_trampoline);
             debug_assert!(inner.events_trampoline.is_none());
             inner.events_trampoline = Some(events_trampoline);
 
-            // Diff and apply the `contents` against our dummy `<div/>`.
+            // Diff and apply the `contents` against our dummy `<div/>`, or
+            // hydrate the existing markup.
             inner.render();
         }
 
@@ -222,6 +363,206 @@ impl Vdom {
         exclusive.render();
     }
 
+    /// Invoke the `event` listener that the current render attached to
+    /// `target`, as if the headless DOM had dispatched a real event, and then
+    /// perform any re-render that the listener scheduled. Only for internal
+    /// testing purposes.
+    ///
+    /// The event does not bubble, and targets inside portals are not
+    /// supported.
+    #[cfg(all(
+        feature = "xxx-unstable-internal-use-only",
+        not(target_arch = "wasm32")
+    ))]
+    pub fn dispatch_event(&self, target: &crate::Element, event: &str) {
+        let mut exclusive = self.inner.exclusive.borrow_mut();
+
+        // The index of each ancestor of `target` among its siblings, from the
+        // container down.
+        let mut path = vec![];
+        let mut node = target.clone();
+        while node != exclusive.container {
+            let parent = node
+                .parent_node()
+                .expect("target should be rendered by this vdom");
+            let index = parent
+                .child_nodes()
+                .iter()
+                .position(|child| *child == node)
+                .unwrap();
+            path.push(index);
+            node = parent;
+        }
+
+        let callback = {
+            let cached_set = exclusive.cached_set.borrow();
+            let root = exclusive
+                .current_root
+                .as_ref()
+                .expect("should have rendered");
+            let mut siblings = slice::from_ref(root);
+            let mut listeners: &[crate::node::Listener] = &[];
+            for index in path.into_iter().rev() {
+                let flat = cached_set.flatten(siblings, &mut |_| {});
+                let mut node = &flat[index];
+                if let crate::node::NodeKind::Cached(ref c) = node.kind {
+                    node = cached_set.get(c.id).0;
+                }
+                match node.kind {
+                    crate::node::NodeKind::Element(el) => {
+                        listeners = el.listeners;
+                        siblings = el.children;
+                    }
+                    _ => panic!("target should be an element rendered by this vdom"),
+                }
+            }
+            let listener = listeners
+                .iter()
+                .find(|listener| listener.event == event)
+                .expect("target should have a listener for the event");
+            // The listener lives in the current render's bump arena or cached
+            // set, neither of which is touched until the next render.
+            unsafe {
+                mem::transmute::<
+                    crate::node::ListenerCallback,
+                    crate::node::ListenerCallback<'static>,
+                >(listener.callback)
+            }
+        };
+
+        callback(
+            exclusive.component_raw_mut(),
+            VdomWeak::new(&self.inner),
+            JsValue::UNDEFINED.unchecked_into(),
+        );
+        if self.inner.shared.render_requested.replace(false) {
+            exclusive.render();
+        }
+    }
+
+    /// Start or stop recording a disassembly of each change list that this
+    /// virtual DOM applies to the physical DOM.
+    ///
+    /// This is a debugging aid: while recording, every render decodes its
+    /// change list, which is not free.
+    pub fn record_change_lists(&self, record: bool) {
+        let mut exclusive = self.inner.exclusive.borrow_mut();
+        exclusive.change_list.set_record_disassemblies(record);
+    }
+
+    /// Get a disassembly of the last change list that this virtual DOM applied
+    /// to the physical DOM while recording change lists.
+    ///
+    /// Returns `None` if `record_change_lists(true)` has not been called, or no
+    /// render has happened since.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn example(vdom: &dodrio::Vdom) {
+    /// vdom.record_change_lists(true);
+    ///
+    /// // Later, after a render...
+    /// if let Some(change_list) = vdom.last_change_list() {
+    ///     println!("{}", change_list);
+    /// }
+    /// # }
+    /// ```
+    pub fn last_change_list(&self) -> Option<crate::Disassembly> {
+        let exclusive = self.inner.exclusive.borrow();
+        exclusive.change_list.last_disassembly().cloned()
+    }
+
+    /// Start profiling this virtual DOM's renders, keeping the profiles of the
+    /// last `history` renders.
+    ///
+    /// Passing zero stops profiling and discards any recorded profiles.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn example(vdom: &dodrio::Vdom) {
+    /// vdom.set_render_profiling(60);
+    ///
+    /// // Later, after some renders...
+    /// for profile in vdom.render_profiles() {
+    ///     println!(
+    ///         "render: {:?}, diff: {:?}, apply: {:?}, instructions: {}",
+    ///         profile.render_time(),
+    ///         profile.diff_time(),
+    ///         profile.apply_time(),
+    ///         profile.instruction_count(),
+    ///     );
+    /// }
+    /// # }
+    /// ```
+    pub fn set_render_profiling(&self, history: usize) {
+        self.inner
+            .shared
+            .profiler
+            .borrow_mut()
+            .set_capacity(history);
+    }
+
+    /// Get the profile of the last render, if profiling is enabled and a
+    /// render has happened since it was enabled.
+    pub fn last_render_profile(&self) -> Option<RenderProfile> {
+        self.inner.shared.profiler.borrow().last().cloned()
+    }
+
+    /// Get the profiles of the most recent renders, oldest first.
+    pub fn render_profiles(&self) -> Vec<RenderProfile> {
+        self.inner
+            .shared
+            .profiler
+            .borrow()
+            .history()
+            .cloned()
+            .collect()
+    }
+
+    /// Keep renders of `Cached` components that are no longer displayed
+    /// around for reuse, for as long as all of the cached renders take up at
+    /// most `budget` bytes.
+    ///
+    /// By default (and with `None`), cached renders are dropped as soon as a
+    /// render no longer displays them, so showing them again means rendering
+    /// them again. With a budget, they are instead evicted least recently used
+    /// first, once the cache grows past the budget. The renders that are
+    /// currently displayed, and the templates that renders are cloned from,
+    /// count towards the budget but are never evicted.
+    ///
+    /// The new budget takes effect at the end of the next render.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn example(vdom: &dodrio::Vdom) {
+    /// // Keep up to 1 MiB of cached renders around.
+    /// vdom.set_cache_budget(Some(1024 * 1024));
+    ///
+    /// // Later, after some renders...
+    /// let stats = vdom.cache_stats();
+    /// println!(
+    ///     "{} cached renders ({} retained) in {} bytes",
+    ///     stats.entries(),
+    ///     stats.retained_entries(),
+    ///     stats.bytes(),
+    /// );
+    /// # }
+    /// ```
+    pub fn set_cache_budget(&self, budget: Option<usize>) {
+        let exclusive = self.inner.exclusive.borrow();
+        exclusive.cached_set.borrow_mut().set_budget(budget);
+    }
+
+    /// Get statistics about this virtual DOM's cache of `Cached` renders.
+    pub fn cache_stats(&self) -> CacheStats {
+        let exclusive = self.inner.exclusive.borrow();
+        let stats = exclusive.cached_set.borrow().stats();
+        stats
+    }
+
     /// Run this virtual DOM and its listeners forever and never unmount it.
     #[inline]
     pub fn forget(self) {
@@ -238,14 +579,13 @@ impl Vdom {
     /// root render component.
     #[inline]
     pub fn unmount(self) -> Box<RootRender> {
-        Rc::try_unwrap(self.inner.clone())
+        let mut exclusive = Rc::try_unwrap(self.inner.clone())
             .map_err(|_| ())
             .unwrap_throw()
             .exclusive
-            .into_inner()
-            .component
-            .take()
-            .unwrap_throw()
+            .into_inner();
+        exclusive.run_unmount_hooks();
+        exclusive.component.take().unwrap_throw()
     }
 }
 
@@ -259,6 +599,16 @@ impl VdomInnerExclusive {
 
     /// Re-render this virtual dom's current component.
     pub(crate) fn render(&mut self) {
+        let owner = self.vdom.upgrade();
+        let profiling = owner
+            .as_ref()
+            .map_or(false, |owner| owner.shared.profiler.borrow().is_enabled());
+        let mut profile = if profiling {
+            Some(RenderProfile::default())
+        } else {
+            None
+        };
+
         unsafe {
             let events_registry = self.events_registry.take().unwrap();
             {
@@ -269,35 +619,86 @@ impl VdomInnerExclusive {
                 dom_buffers[1].reset();
 
                 // Render the new current contents into the inactive bump arena.
+                let stopwatch = Stopwatch::start(profiling);
                 let mut cx =
                     RenderContext::new(&dom_buffers[1], &self.cached_set, &mut self.templates);
-                let new_contents = self.component.as_ref().unwrap_throw().render(&mut cx);
+                let new_contents = {
+                    // Record the `Signal`s that the component reads, so that
+                    // writing them re-renders this vdom.
+                    let _tracking = TrackingScope::root(VdomWeak {
+                        inner: self.vdom.clone(),
+                    });
+                    self.component.as_ref().unwrap_throw().render(&mut cx)
+                };
                 let new_contents = extend_node_lifetime(new_contents);
+                let render_time = stopwatch.elapsed();
+
+                #[cfg(feature = "debug-checks")]
+                crate::debug_checks::validate(&self.cached_set.borrow(), &new_contents);
 
-                // Diff the old contents with the new contents.
-                let old_contents = self.current_root.take().unwrap();
+                // Diff the old contents with the new contents. If there are no
+                // old contents, then this is the first render of a hydrating
+                // vdom, and we hydrate the existing markup instead.
+                //
+                // The change list interpreter starts at the container, and
+                // the root is diffed as the container's only child, unless it
+                // is a fragment, whose children are spliced in.
+                let old_contents = self.current_root.take();
                 let mut cache_roots;
                 {
+                    let stopwatch = Stopwatch::start(profiling);
                     let cached_set = self.cached_set.borrow();
                     cache_roots = cached_set.new_roots_set();
                     let mut change_list = self.change_list.builder();
-                    crate::diff::diff(
-                        &cached_set,
-                        &mut change_list,
-                        &mut registry,
-                        &old_contents,
-                        &new_contents,
-                        &mut cache_roots,
-                    );
-
-                    // Tell JS to apply our diff-generated changes to the physical DOM!
-                    change_list.finish();
+                    match old_contents {
+                        Some(old_contents) => crate::diff::diff_children(
+                            &cached_set,
+                            &mut change_list,
+                            &mut registry,
+                            slice::from_ref(&old_contents),
+                            slice::from_ref(&new_contents),
+                            &mut cache_roots,
+                        ),
+                        None => crate::hydrate::hydrate_children(
+                            &cached_set,
+                            &mut change_list,
+                            &mut registry,
+                            &self.container,
+                            slice::from_ref(&new_contents),
+                            &mut cache_roots,
+                        ),
+                    }
+                    let diff_time = stopwatch.elapsed();
+
+                    if let Some(profile) = &mut profile {
+                        profile.render_time = render_time;
+                        profile.diff_time = diff_time;
+                    }
+
+                    // Invoke the unmount hooks while their elements are still in
+                    // the physical DOM, then tell JS to apply our diff-generated
+                    // changes to the physical DOM, and finally invoke the mount
+                    // hooks now that their elements exist.
+                    let mut lifecycle = mem::take(change_list.lifecycle());
+                    let vdom = VdomWeak {
+                        inner: self.vdom.clone(),
+                    };
+                    let component = &mut **self.component.as_mut().unwrap_throw();
+                    lifecycle.run_unmounting(component, &vdom);
+                    change_list.finish(profile.as_mut());
+                    lifecycle.run_mounted(component, &vdom);
                 }
 
                 {
                     // Clean up unused cached renders.
                     let mut cached_set = self.cached_set.borrow_mut();
                     cached_set.gc(&mut registry, cache_roots);
+
+                    let (cache_hits, cache_misses) = cached_set.take_hit_counts();
+                    if let Some(profile) = &mut profile {
+                        profile.cache_hits = cache_hits;
+                        profile.cache_misses = cache_misses;
+                    }
                 }
 
                 // Swap the buffers to make the bump arena with the new contents the
@@ -308,6 +709,26 @@ impl VdomInnerExclusive {
 
             self.events_registry = Some(events_registry);
         }
+
+        if let (Some(owner), Some(profile)) = (owner, profile) {
+            owner.shared.profiler.borrow_mut().push(profile);
+        }
+    }
+
+    // Invoke the unmount hooks of everything that is currently rendered.
+    fn run_unmount_hooks(&mut self) {
+        if let Some(current_root) = &self.current_root {
+            let mut lifecycle = LifecycleHooks::default();
+            unsafe {
+                // Safety: the current root is alive until it is replaced by the
+                // next render, and we invoke the hooks right away.
+                lifecycle.unmounting_subtree(&self.cached_set.borrow(), current_root);
+            }
+            let vdom = VdomWeak {
+                inner: self.vdom.clone(),
+            };
+            lifecycle.run_unmounting(self.component_raw_mut(), &vdom);
+        }
     }
 
     fn swap_buffers(&mut self, mut dom_buffers: [Bump; 2]) {
@@ -323,14 +744,71 @@ impl VdomInnerExclusive {
     }
 }
 
-fn request_animation_frame(f: &Closure<FnMut()>) {
+// Request an animation frame in which to render the given virtual DOM.
+fn schedule_render(inner: &Rc<VdomInner>) -> ScheduledRender {
+    let mut resolve_and_reject = None;
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        resolve_and_reject = Some((resolve, reject));
+    });
+    let (resolve, reject) = resolve_and_reject.unwrap_throw();
+
+    let vdom = VdomWeak::new(inner);
+    let resolve2 = resolve.clone();
+    let frame = with_animation_frame(move || match vdom.inner.upgrade() {
+        None => {
+            warn!("VdomWeak::render: vdom unmounted before we could render");
+            let r = reject.call0(&JsValue::null());
+            debug_assert!(r.is_ok());
+        }
+        Some(inner) => {
+            let mut exclusive = inner.exclusive.borrow_mut();
+            exclusive.render();
+
+            // We did the render, so take the scheduled render away and let
+            // future `render` calls request new animation frames.
+            let _ = inner.shared.render_scheduled.take();
+
+            let r = resolve2.call0(&JsValue::null());
+            debug_assert!(r.is_ok());
+        }
+    });
+
+    ScheduledRender {
+        promise,
+        resolve,
+        frame,
+    }
+}
+
+fn request_animation_frame(f: &Closure<FnMut()>) -> i32 {
     web_sys::window()
         .expect_throw("should have a window")
         .request_animation_frame(f.as_ref().unchecked_ref())
-        .expect_throw("should register `requestAnimationFrame` OK");
+        .expect_throw("should register `requestAnimationFrame` OK")
 }
 
-fn with_animation_frame<F>(mut f: F)
+type AnimationFrameClosure = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+
+/// A handle to a callback that was registered with `requestAnimationFrame`.
+#[derive(Debug)]
+struct AnimationFrame {
+    id: i32,
+    closure: AnimationFrameClosure,
+}
+
+impl AnimationFrame {
+    /// Cancel the animation frame callback, if it hasn't run yet.
+    fn cancel(&self) {
+        if self.closure.borrow_mut().take().is_some() {
+            web_sys::window()
+                .expect_throw("should have a window")
+                .cancel_animation_frame(self.id)
+                .expect_throw("should cancel `requestAnimationFrame` OK");
+        }
+    }
+}
+
+fn with_animation_frame<F>(mut f: F) -> AnimationFrame
 where
     F: 'static + FnMut(),
 {
@@ -341,9 +819,10 @@ where
         *g.borrow_mut() = None;
         f();
     }) as Box<FnMut()>);
-    request_animation_frame(&f);
+    let id = request_animation_frame(&f);
 
     *h.borrow_mut() = Some(f);
+    AnimationFrame { id, closure: h }
 }
 
 /// An operation failed because the virtual DOM was already dropped and
@@ -368,6 +847,18 @@ impl VdomWeak {
         }
     }
 
+    /// Get the address of the virtual DOM, for comparing weak handles.
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> usize {
+        self.inner.as_ptr() as usize
+    }
+
+    /// Get the dispatcher for messages to the root rendering component, if it
+    /// handles messages.
+    pub(crate) fn dispatcher(&self) -> Option<Dispatcher> {
+        self.inner.upgrade()?.shared.dispatcher.get()
+    }
+
     /// Replace the root rendering component with the new `root`.
     ///
     /// Returns a future that resolves to the *old* root component.
@@ -423,7 +914,24 @@ impl VdomWeak {
     /// `render` instead.
     pub fn schedule_render(&self) {
         debug!("VdomWeak::schedule_render");
-        wasm_bindgen_futures::spawn_local(self.render().map_err(|_| ()));
+
+        #[cfg(all(
+            feature = "xxx-unstable-internal-use-only",
+            not(target_arch = "wasm32")
+        ))]
+        {
+            if let Some(inner) = self.inner.upgrade() {
+                inner.shared.render_requested.set(true);
+            }
+        }
+
+        #[cfg(not(all(
+            feature = "xxx-unstable-internal-use-only",
+            not(target_arch = "wasm32")
+        )))]
+        {
+            wasm_bindgen_futures::spawn_local(self.render().map_err(|_| ()));
+        }
     }
 
     /// Schedule a render to occur during the next animation frame and return a
@@ -436,36 +944,166 @@ impl VdomWeak {
             .and_then(|inner| inner.ok_or(()))
             .map_err(|_| VdomDroppedError {})
             .and_then(|inner| {
-                let promise = inner.shared.render_scheduled.take().unwrap_or_else(|| {
-                    js_sys::Promise::new(&mut |resolve, reject| {
-                        let vdom = VdomWeak {
-                            inner: Rc::downgrade(&inner),
-                        };
-                        with_animation_frame(move || match vdom.inner.upgrade() {
-                            None => {
-                                warn!("VdomWeak::render: vdom unmounted before we could render");
-                                let r = reject.call0(&JsValue::null());
-                                debug_assert!(r.is_ok());
-                            }
-                            Some(inner) => {
-                                let mut exclusive = inner.exclusive.borrow_mut();
-                                exclusive.render();
-
-                                // We did the render, so take the promise away
-                                // and let future `render` calls request new
-                                // animation frames.
-                                let _ = inner.shared.render_scheduled.take();
-
-                                let r = resolve.call0(&JsValue::null());
-                                debug_assert!(r.is_ok());
-                            }
-                        });
-                    })
-                });
-                inner.shared.render_scheduled.set(Some(promise.clone()));
+                let scheduled = inner
+                    .shared
+                    .render_scheduled
+                    .take()
+                    .unwrap_or_else(|| schedule_render(&inner));
+                let promise = scheduled.promise.clone();
+                inner.shared.render_scheduled.set(Some(scheduled));
                 JsFuture::from(promise)
                     .map(|_| ())
                     .map_err(|_| VdomDroppedError {})
             })
     }
+
+    /// Like `set_component`, but returns a `std::future::Future` that can be
+    /// `.await`ed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{RootRender, VdomWeak};
+    ///
+    /// async fn replace_root(vdom: VdomWeak, root: Box<dyn RootRender>) {
+    ///     let old = vdom.set_component_async(root).await.unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn set_component_async(
+        self,
+        root: Box<dyn RootRender>,
+    ) -> impl std::future::Future<Output = Result<Box<dyn RootRender + 'static>, VdomDroppedError>>
+    {
+        StdFuture::new(self.set_component(root))
+    }
+
+    /// Like `with_component`, but returns a `std::future::Future` that can be
+    /// `.await`ed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use dodrio::{RootRender, VdomWeak};
+    ///
+    /// # struct Counter { count: u32 }
+    /// # impl dodrio::Render for Counter {
+    /// #     fn render<'a>(&self, cx: &mut dodrio::RenderContext<'a>) -> dodrio::Node<'a> {
+    /// #         unimplemented!()
+    /// #     }
+    /// # }
+    /// async fn increment(vdom: VdomWeak) {
+    ///     vdom.with_component_async(|root| {
+    ///         root.unwrap_mut::<Counter>().count += 1;
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    ///     vdom.render_async().await.unwrap();
+    /// }
+    /// ```
+    pub fn with_component_async<F, T>(
+        &self,
+        f: F,
+    ) -> impl std::future::Future<Output = Result<T, VdomDroppedError>>
+    where
+        F: 'static + FnOnce(&mut dyn RootRender) -> T,
+    {
+        StdFuture::new(self.with_component(f))
+    }
+
+    /// Like `render`, but returns a `std::future::Future` that can be
+    /// `.await`ed.
+    pub fn render_async(&self) -> impl std::future::Future<Output = Result<(), VdomDroppedError>> {
+        StdFuture::new(self.render())
+    }
+
+    /// Render immediately, rather than waiting for the next animation frame.
+    ///
+    /// Any render that was already scheduled is cancelled, and the futures
+    /// returned by `render` for it resolve once this render has finished.
+    ///
+    /// If the virtual DOM is already borrowed, for example when this is called
+    /// from inside one of its event listeners, then it cannot be rendered right
+    /// now, and a render is scheduled for the next animation frame instead.
+    pub fn render_sync(&self) -> Result<(), VdomDroppedError> {
+        let inner = self.inner.upgrade().ok_or(VdomDroppedError {})?;
+
+        let mut exclusive = match inner.exclusive.try_borrow_mut() {
+            Ok(exclusive) => exclusive,
+            Err(_) => {
+                debug!("VdomWeak::render_sync: vdom is borrowed, scheduling a render instead");
+                self.schedule_render();
+                return Ok(());
+            }
+        };
+
+        let scheduled = inner.shared.render_scheduled.take();
+        if let Some(scheduled) = &scheduled {
+            scheduled.frame.cancel();
+        }
+
+        exclusive.render();
+        drop(exclusive);
+
+        if let Some(scheduled) = scheduled {
+            let r = scheduled.resolve.call0(&JsValue::null());
+            debug_assert!(r.is_ok());
+        }
+        Ok(())
+    }
+
+    /// Like `Vdom::set_render_profiling`.
+    pub fn set_render_profiling(&self, history: usize) -> Result<(), VdomDroppedError> {
+        let inner = self.inner.upgrade().ok_or(VdomDroppedError {})?;
+        inner.shared.profiler.borrow_mut().set_capacity(history);
+        Ok(())
+    }
+
+    /// Like `Vdom::last_render_profile`.
+    ///
+    /// Unlike most `VdomWeak` methods, this can be called synchronously from
+    /// inside event listeners and lifecycle hooks.
+    pub fn last_render_profile(&self) -> Result<Option<RenderProfile>, VdomDroppedError> {
+        let inner = self.inner.upgrade().ok_or(VdomDroppedError {})?;
+        let profile = inner.shared.profiler.borrow().last().cloned();
+        Ok(profile)
+    }
+
+    /// Like `Vdom::render_profiles`.
+    pub fn render_profiles(&self) -> Result<Vec<RenderProfile>, VdomDroppedError> {
+        let inner = self.inner.upgrade().ok_or(VdomDroppedError {})?;
+        let profiles = inner.shared.profiler.borrow().history().cloned().collect();
+        Ok(profiles)
+    }
+}
+
+#[cfg(all(test, feature = "xxx-unstable-internal-use-only"))]
+mod tests {
+    use crate::headless::testing::*;
+    use crate::{builder::*, bumpalo, Node, Render, RenderContext};
+    use std::{cell::Cell, rc::Rc};
+
+    struct Counter(Rc<Cell<u32>>);
+
+    impl Render for Counter {
+        fn render<'a>(&self, cx: &mut RenderContext<'a>) -> Node<'a> {
+            let count = bumpalo::format!(in cx.bump, "{}", self.0.get()).into_bump_str();
+            p(&cx).children([text(count)]).finish()
+        }
+    }
+
+    #[test]
+    fn render_sync() {
+        let count = Rc::new(Cell::new(0));
+        let (container, vdom) = mount(Counter(count.clone()));
+        assert_eq!(container.inner_html(), "<p>0</p>");
+
+        count.set(1);
+        vdom.weak().render_sync().unwrap();
+        assert_eq!(container.inner_html(), "<p>1</p>");
+
+        let weak = vdom.weak();
+        drop(vdom);
+        assert!(weak.render_sync().is_err());
+    }
 }